@@ -7,17 +7,89 @@ extern crate signal_notify as sig;
 extern crate taskmaster;
 
 use failure::Error;
+use nix::sys::termios;
 use sig::Signal;
 use std::io::{self, stdin, stdout, BufRead, Read, Write};
 use std::net::TcpStream;
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
-use std::time::Duration;
 use taskmaster::api::{self, ApiArg, ApiArgKind, ApiKind, ApiRequestBuilder};
 use taskmaster::config::*;
+use taskmaster::ffi;
 use taskmaster::log::*;
 
+/// Ctrl-] ends a foreground session on a tty
+const DETACH_KEY: u8 = 0x1d;
+
+fn input_chunk(data: &[u8]) -> Vec<u8> {
+    let mut chunk = vec![api::FG_INPUT];
+    chunk.extend_from_slice(data);
+    chunk
+}
+
+fn send_winsize(stream: &Mutex<TcpStream>) -> io::Result<()> {
+    let (rows, cols) = ffi::get_winsize(0).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    let chunk = [
+        api::FG_RESIZE,
+        (rows >> 8) as u8,
+        rows as u8,
+        (cols >> 8) as u8,
+        cols as u8,
+    ];
+    api::send_data(&mut *stream.lock().unwrap(), &chunk)
+}
+
+/// Forward raw terminal input until the detach key, window size changes are sent along
+fn handle_fg_tty(stdin_stream: TcpStream) -> Result<(), Error> {
+    let saved = termios::tcgetattr(0)?;
+    let mut raw = saved.clone();
+    termios::cfmakeraw(&mut raw);
+    info!("attached to tty, press Ctrl-] to detach");
+    termios::tcsetattr(0, termios::SetArg::TCSANOW, &raw)?;
+    let stdin_stream = Arc::new(Mutex::new(stdin_stream));
+    let _ = send_winsize(&stdin_stream);
+    let winch_recv = sig::notify(&[Signal::WINCH]);
+    let winch_stream = stdin_stream.clone();
+    thread::spawn(move || {
+        for _ in winch_recv.iter() {
+            if send_winsize(&winch_stream).is_err() {
+                return;
+            }
+        }
+    });
+    loop {
+        let mut buf = [0; 512];
+        match stdin().read(&mut buf) {
+            Ok(0) => break,
+            Ok(sz) => {
+                let end = buf[0..sz].iter().position(|&b| b == DETACH_KEY);
+                let data = &buf[0..end.unwrap_or(sz)];
+                if !data.is_empty() {
+                    let chunk = input_chunk(data);
+                    if let Err(e) = api::send_data(&mut *stdin_stream.lock().unwrap(), &chunk) {
+                        error!("{:?}", e);
+                        break;
+                    }
+                }
+                if end.is_some() {
+                    break;
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => {
+                error!("{:?}", e);
+                break;
+            }
+        }
+    }
+    let _ = api::send_data(&mut *stdin_stream.lock().unwrap(), &[]);
+    termios::tcsetattr(0, termios::SetArg::TCSANOW, &saved)?;
+    print!("\r\n");
+    Ok(())
+}
+
 fn handle_fg(stream: &mut TcpStream, sign_recv: &mpsc::Receiver<Signal>) -> Result<(), Error> {
+    let tty = api::recv_data(stream)? == b"tty";
     let stdin_port = String::from_utf8(api::recv_data(stream)?)?.parse()?;
     let mut stdin_stream = match TcpStream::connect(("127.0.0.1", stdin_port)) {
         Ok(s) => s,
@@ -34,26 +106,31 @@ fn handle_fg(stream: &mut TcpStream, sign_recv: &mpsc::Receiver<Signal>) -> Resu
             bail!(e);
         }
     };
-    stdout_stream.set_nonblocking(true).unwrap();
     thread::spawn(move || loop {
-        let mut buf = [0; 512];
-        match stdout_stream.read(&mut buf) {
-            Ok(sz) => {
-                print!("{}", ::std::str::from_utf8(&buf[0..sz]).unwrap());
+        match api::recv_data(&mut stdout_stream) {
+            Ok(ref data) if data.is_empty() => return,
+            Ok(data) => {
+                let _ = stdout().write_all(&data);
+                let _ = stdout().flush();
             }
-            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
             Err(e) => {
                 error!("{:#?}", e);
                 return;
             }
         }
-        thread::sleep(Duration::from_millis(10));
     });
+    if tty {
+        return handle_fg_tty(stdin_stream);
+    }
     loop {
         let mut buf = [0; 512];
         match stdin().read(&mut buf) {
+            Ok(0) => {
+                let _ = api::send_data(&mut stdin_stream, &[]);
+                break;
+            }
             Ok(sz) => {
-                api::send_data(&mut stdin_stream, &buf[0..sz]).unwrap();
+                api::send_data(&mut stdin_stream, &input_chunk(&buf[0..sz])).unwrap();
             }
             Err(ref e) if e.kind() == io::ErrorKind::Interrupted => break,
             Err(e) => {
@@ -179,6 +256,19 @@ fn main_loop(
                 let data = String::from_utf8(api::recv_data(&mut stream)?)?;
                 println!("{}", data.trim());
             }
+            "last_run" => {
+                ApiRequestBuilder::new(ApiKind::LastRun)
+                    .args(
+                        parsed[1..]
+                            .iter()
+                            .map(|a| ApiArg::new(ApiArgKind::Target, a.to_owned()))
+                            .collect(),
+                    )
+                    .build()
+                    .send(&mut stream)?;
+                let data = String::from_utf8(api::recv_data(&mut stream)?)?;
+                println!("{}", data.trim());
+            }
             "kill" => {
                 ApiRequestBuilder::new(ApiKind::Kill)
                     .args(
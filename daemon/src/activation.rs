@@ -0,0 +1,126 @@
+//! Start processes on the first connection to their sockets and stop them once idle
+
+use nix::errno::Errno;
+use nix::poll::*;
+use process::*;
+use shutdown::is_shutting_down;
+use socket;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use taskmaster::config::StartOn;
+use ProcessSync;
+
+/// Delay between two checks of idle processes in millis
+const TICK_MS: i32 = 1000;
+
+/// Start watching sockets of processes started on connection
+pub fn start(processes: Arc<Vec<ProcessSync>>) {
+    let on_demand: Vec<ProcessSync> = processes
+        .iter()
+        .filter(|p| p.read().unwrap().config().start_on == StartOn::Socket)
+        .cloned()
+        .collect();
+    for process in on_demand.iter() {
+        let process = process.read().unwrap();
+        if process.sockets().is_empty() {
+            warn!(
+                "process {} starts on socket but has no sockets, it never starts",
+                process.proc_name()
+            );
+        }
+    }
+    if on_demand.is_empty() {
+        return;
+    }
+    thread::spawn(move || run(&on_demand));
+}
+
+fn run(processes: &[ProcessSync]) {
+    let mut idle_since: Vec<Option<Instant>> = vec![None; processes.len()];
+    loop {
+        if is_shutting_down() {
+            return;
+        }
+        let mut fds = Vec::new();
+        for (idx, process) in processes.iter().enumerate() {
+            let inactive = process.read().unwrap().is_inactive();
+            if inactive {
+                idle_since[idx] = None;
+                for socket in process.read().unwrap().sockets() {
+                    fds.push((socket.fd(), idx));
+                }
+            } else {
+                check_idle(process, &mut idle_since[idx]);
+            }
+        }
+        let mut poll_fds = fds
+            .iter()
+            .map(|&(fd, _)| PollFd::new(fd, POLLIN))
+            .collect::<Vec<PollFd>>();
+        match poll(&mut poll_fds, TICK_MS) {
+            Ok(_) => {}
+            Err(::nix::Error::Sys(Errno::EINTR)) => continue,
+            Err(e) => {
+                critical!("socket activation stopped: {}", e);
+                return;
+            }
+        }
+        let mut started = Vec::new();
+        for (&(_, idx), poll_fd) in fds.iter().zip(poll_fds.iter()) {
+            match poll_fd.revents() {
+                Some(events) if !events.is_empty() => {}
+                _ => continue,
+            }
+            if started.contains(&idx) {
+                continue;
+            }
+            started.push(idx);
+            // the process may have been started since its sockets were polled
+            if !processes[idx].read().unwrap().claim_supervisor() {
+                continue;
+            }
+            start_on_connection(&processes[idx]);
+            let process = processes[idx].clone();
+            thread::spawn(move || supervise(process));
+        }
+    }
+}
+
+/// Stop process once it had no connection for its idle timeout
+fn check_idle(process: &ProcessSync, idle_since: &mut Option<Instant>) {
+    let (pid, timeout) = {
+        let process = process.read().unwrap();
+        let timeout = match process.config().idle_timeout_secs {
+            Some(secs) => Duration::from_secs(secs),
+            None => return,
+        };
+        let state = process.get_state().clone();
+        match state {
+            ProcessState::Running(pid) => {
+                if socket::has_connections(&process.members(), process.sockets()) {
+                    *idle_since = None;
+                    return;
+                }
+                (pid, timeout)
+            }
+            _ => {
+                *idle_since = None;
+                return;
+            }
+        }
+    };
+    let since = *idle_since.get_or_insert_with(Instant::now);
+    if since.elapsed() < timeout {
+        return;
+    }
+    *idle_since = None;
+    info!(
+        "stopping process {} on pid {}, idle for {}s",
+        process.read().unwrap().proc_name(),
+        pid,
+        timeout.as_secs()
+    );
+    let process = process.clone();
+    thread::spawn(move || stop(&process));
+}
@@ -0,0 +1,150 @@
+//! Place processes in cgroup v2 groups to enforce limits and kill their descendants
+
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use taskmaster::config::ProcessConfig;
+
+/// Controllers enabled for the cgroups of processes
+const CONTROLLERS: [&str; 3] = ["memory", "cpu", "pids"];
+
+/// Period of cpu.max in usecs
+const CPU_PERIOD: u64 = 100000;
+
+fn write_file(path: &Path, data: &str) -> io::Result<()> {
+    OpenOptions::new()
+        .write(true)
+        .open(path)
+        .and_then(|mut f| f.write_all(data.as_bytes()))
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+fn read_file(path: &Path) -> io::Result<String> {
+    let mut buf = String::new();
+    File::open(path)?.read_to_string(&mut buf)?;
+    Ok(buf)
+}
+
+/// Create parent cgroup and enable controllers, None if cgroup v2 is not usable
+pub fn init(parent: &Path) -> Option<PathBuf> {
+    let root = parent.parent()?;
+    if !root.join("cgroup.controllers").exists() {
+        info!(
+            "cgroup v2 not available at {}, cgroups disabled",
+            root.display()
+        );
+        return None;
+    }
+    if let Err(e) = fs::create_dir_all(parent) {
+        warn!(
+            "cannot create cgroup {}, cgroups disabled",
+            parent.display()
+        );
+        trace!("error: {}", e);
+        return None;
+    }
+    let available = read_file(&parent.join("cgroup.controllers")).unwrap_or(String::new());
+    for controller in CONTROLLERS.iter() {
+        if !available.split_whitespace().any(|c| c == *controller) {
+            debug!("cgroup controller {} not available", controller);
+            continue;
+        }
+        let subtree_control = parent.join("cgroup.subtree_control");
+        if let Err(e) = write_file(&subtree_control, &format!("+{}", controller)) {
+            warn!("cannot enable cgroup controller {}", controller);
+            trace!("error: {}", e);
+        }
+    }
+    Some(parent.to_path_buf())
+}
+
+/// Cgroup of a process
+#[derive(Debug)]
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    /// Create handle on cgroup named after process under parent
+    pub fn new(parent: &Path, name: &str) -> Cgroup {
+        Cgroup {
+            path: parent.join(name),
+        }
+    }
+
+    /// Create cgroup if needed and apply limits of process
+    pub fn setup(&self, config: &ProcessConfig) -> io::Result<()> {
+        fs::create_dir_all(&self.path)?;
+        if let Some(max) = config.memory_max {
+            write_file(&self.path.join("memory.max"), &max.to_string())?;
+        }
+        if let Some(percent) = config.cpu_max {
+            let quota = percent as u64 * CPU_PERIOD / 100;
+            write_file(
+                &self.path.join("cpu.max"),
+                &format!("{} {}", quota, CPU_PERIOD),
+            )?;
+        }
+        if let Some(max) = config.pids_max {
+            write_file(&self.path.join("pids.max"), &max.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Move calling process into cgroup
+    pub fn enter(&self) -> io::Result<()> {
+        write_file(&self.path.join("cgroup.procs"), "0")
+    }
+
+    /// Number of processes of cgroup killed by the oom killer
+    pub fn oom_kills(&self) -> u64 {
+        read_file(&self.path.join("memory.events"))
+            .ok()
+            .and_then(|events| {
+                events
+                    .lines()
+                    .filter_map(|line| {
+                        let mut fields = line.split_whitespace();
+                        match (fields.next(), fields.next()) {
+                            (Some("oom_kill"), Some(count)) => count.parse().ok(),
+                            _ => None,
+                        }
+                    })
+                    .next()
+            })
+            .unwrap_or(0)
+    }
+
+    /// Get pids of processes in cgroup
+    pub fn procs(&self) -> Vec<Pid> {
+        read_file(&self.path.join("cgroup.procs"))
+            .unwrap_or(String::new())
+            .lines()
+            .filter_map(|line| line.trim().parse().ok())
+            .map(Pid::from_raw)
+            .collect()
+    }
+
+    /// Kill every process in cgroup
+    pub fn kill_all(&self) {
+        if write_file(&self.path.join("cgroup.kill"), "1").is_ok() {
+            return;
+        }
+        // cgroup.kill is missing before linux 5.14, kill until no process is left
+        for _ in 0..10 {
+            let procs = self.procs();
+            if procs.is_empty() {
+                return;
+            }
+            for pid in procs {
+                let _ = kill(pid, Signal::SIGKILL);
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        warn!("processes left in cgroup {}", self.path.display());
+    }
+}
@@ -0,0 +1,76 @@
+//! Run checks against processes
+
+use process::run_helper;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::os::unix::fs::FileTypeExt;
+use std::time::Duration;
+use taskmaster::config::Check;
+use ProcessSync;
+
+/// Run check once, error tells why it failed
+///
+/// The process lock must not be held, command checks need the reaper which takes it.
+pub fn run(process: &ProcessSync, check: &Check, timeout: Duration) -> Result<(), String> {
+    match *check {
+        Check::Tcp(ref addr) => {
+            let addrs = addr.to_socket_addrs().map_err(|e| e.to_string())?;
+            for sock_addr in addrs {
+                if TcpStream::connect_timeout(&sock_addr, timeout).is_ok() {
+                    return Ok(());
+                }
+            }
+            Err(format!("cannot connect to {}", addr))
+        }
+        Check::UnixSocket(ref path) => match path.metadata() {
+            Ok(ref meta) if meta.file_type().is_socket() => Ok(()),
+            Ok(_) => Err(format!("{} is not a socket", path.display())),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        },
+        Check::File(ref path) => {
+            if path.exists() {
+                Ok(())
+            } else {
+                Err(format!("{} does not exist", path.display()))
+            }
+        }
+        Check::Command(ref command) => match run_helper(process, command, timeout)? {
+            0 => Ok(()),
+            code => Err(format!("`{}` exited with code {}", command, code)),
+        },
+        Check::Http(ref url) => http_get(url, timeout),
+    }
+}
+
+/// Send a GET request to `host:port/path`, pass on 2xx and 3xx status
+fn http_get(url: &str, timeout: Duration) -> Result<(), String> {
+    let (addr, path) = match url.find('/') {
+        Some(idx) => (&url[..idx], &url[idx..]),
+        None => (url, "/"),
+    };
+    let sock_addr = match addr.to_socket_addrs().map_err(|e| e.to_string())?.next() {
+        Some(sock_addr) => sock_addr,
+        None => return Err(format!("cannot resolve {}", addr)),
+    };
+    let mut stream = TcpStream::connect_timeout(&sock_addr, timeout).map_err(|e| e.to_string())?;
+    stream
+        .set_read_timeout(Some(timeout))
+        .map_err(|e| e.to_string())?;
+    stream
+        .set_write_timeout(Some(timeout))
+        .map_err(|e| e.to_string())?;
+    write!(stream, "GET {} HTTP/1.0\r\nHost: {}\r\n\r\n", path, addr).map_err(|e| e.to_string())?;
+    let mut status_line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut status_line)
+        .map_err(|e| e.to_string())?;
+    match status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+    {
+        Some(code) if code >= 200 && code < 400 => Ok(()),
+        Some(code) => Err(format!("{} answered with status {}", url, code)),
+        None => Err(format!("invalid response from {}", url)),
+    }
+}
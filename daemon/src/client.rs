@@ -2,15 +2,26 @@
 
 use ProcessSync;
 
-use process::Process;
-use std::io;
+use nix::unistd::close;
+use process::{format_duration, stop};
+use shutdown::shutdown;
 use std::net::{TcpListener, TcpStream};
-use std::sync::{Arc, RwLockReadGuard};
+use std::sync::{mpsc, Arc};
 use std::thread;
-use std::time::Duration;
 use taskmaster::api::*;
+use taskmaster::ffi::{self, LocalTime};
 
-pub fn handle_fg<'a>(stream: &mut TcpStream, process: RwLockReadGuard<'a, Process>) {
+/// Attach a client to process, forwarding its stdin and stdout until one side closes
+///
+/// The client is told first whether the process runs in a tty, then gets the ports to
+/// connect to. Chunks on stdin start with FG_INPUT or FG_RESIZE.
+pub fn handle_fg(stream: &mut TcpStream, process: ProcessSync) {
+    let mode: &[u8] = if process.read().unwrap().holder().is_pty() {
+        b"tty"
+    } else {
+        b"pipe"
+    };
+    send_data(stream, mode).unwrap();
     let listener_stdin = TcpListener::bind("127.0.0.1:0").unwrap();
     let stdin_addr = listener_stdin.local_addr().unwrap();
     let listener_stdout = TcpListener::bind("127.0.0.1:0").unwrap();
@@ -19,32 +30,54 @@ pub fn handle_fg<'a>(stream: &mut TcpStream, process: RwLockReadGuard<'a, Proces
     send_data(stream, stdout_addr.port().to_string().as_bytes()).unwrap();
     let (mut stream_in, _) = listener_stdin.accept().unwrap();
     let (mut stream_out, _) = listener_stdout.accept().unwrap();
-    stream_in.set_nonblocking(true).unwrap();
-    stream_out.set_nonblocking(true).unwrap();
-    loop {
-        match recv_data(&mut stream_in) {
-            Ok(s) => {
-                blather!("received stdin");
-                process.holder().write_stdin(&s).unwrap();
-            }
-            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
-            Err(ref e) => {
-                warn!("{}", e);
-                break;
-            }
-        }
-        let readed = process.holder().read_stdout();
-        if readed.len() > 0 {
-            match send_data(&mut stream_out, &readed[0..readed.len()]) {
-                Ok(_) => {}
-                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+    let (sender, receiver) = mpsc::channel();
+    process.read().unwrap().holder().subscribe(sender.clone());
+    thread::spawn(move || {
+        loop {
+            match recv_data(&mut stream_in) {
+                Ok(ref s) if s.is_empty() => break,
+                Ok(s) => match s[0] {
+                    FG_INPUT => {
+                        blather!("received stdin");
+                        // a child not reading its stdin must not block the holder, the
+                        // io loop needs it to drain output of every process
+                        let stdin = process.read().unwrap().holder().dup_stdin();
+                        if let Some(fd) = stdin {
+                            let written = ffi::write_all(fd, &s[1..]);
+                            let _ = close(fd);
+                            if let Err(e) = written {
+                                warn!("{}", e);
+                                break;
+                            }
+                        }
+                    }
+                    FG_RESIZE if s.len() == 5 => {
+                        let rows = (s[1] as u16) << 8 | s[2] as u16;
+                        let cols = (s[3] as u16) << 8 | s[4] as u16;
+                        trace!("resizing tty to {}x{}", cols, rows);
+                        if let Err(e) = process.read().unwrap().holder().resize(rows, cols) {
+                            warn!("cannot resize tty: {}", e);
+                        }
+                    }
+                    _ => warn!("invalid foreground chunk"),
+                },
                 Err(e) => {
-                    warn!("{}", e);
+                    trace!("{}", e);
                     break;
                 }
             }
         }
-        thread::sleep(Duration::from_millis(10));
+        // an empty chunk ends the session
+        let _ = sender.send(Vec::new());
+    });
+    for data in receiver.iter() {
+        if data.is_empty() {
+            break;
+        }
+        if let Err(e) = send_data(&mut stream_out, &data) {
+            warn!("{}", e);
+            break;
+        }
     }
 }
 
@@ -65,7 +98,7 @@ pub fn handle_client(mut stream: TcpStream, processes: Arc<Vec<ProcessSync>>) {
         match req.kind() {
             &ApiKind::Shutdown => {
                 warn!("shutdown instruction from {}", addr);
-                break;
+                shutdown(&processes);
             }
             &ApiKind::Status => {
                 info!("status request from {}", addr);
@@ -74,14 +107,57 @@ pub fn handle_client(mut stream: TcpStream, processes: Arc<Vec<ProcessSync>>) {
                     let mut process = process.read().unwrap();
                     let name = process.proc_name().to_owned();
                     let state = process.get_state();
-                    data.push_str(&format!("{} {:?}\n", name, *state));
+                    let mut line = format!("{} {:?}", name, *state);
+                    if let Some(health) = process.health() {
+                        line.push_str(&format!(" {:?}", health));
+                    }
+                    {
+                        let notify = process.notify_state();
+                        if let Some(pid) = notify.main_pid {
+                            line.push_str(&format!(" main pid {}", pid));
+                        }
+                        if let Some(ref status) = notify.status {
+                            line.push_str(&format!(" status {:?}", status));
+                        }
+                    }
+                    let orphans = process.orphans();
+                    if !orphans.is_empty() {
+                        let pids: Vec<String> = orphans.iter().map(|pid| pid.to_string()).collect();
+                        line.push_str(&format!(" orphans {}", pids.join(",")));
+                    }
+                    let overlaps = process.overlaps();
+                    if !overlaps.is_empty() {
+                        let pids: Vec<String> =
+                            overlaps.iter().map(|pid| pid.to_string()).collect();
+                        line.push_str(&format!(" overlapping {}", pids.join(",")));
+                    }
+                    if process.config().schedule.is_some() || process.is_oneshot() {
+                        if let Some(run) = process.last_run() {
+                            line.push_str(&format!(
+                                " last run {:?} in {}",
+                                run.state,
+                                format_duration(run.duration)
+                            ));
+                        }
+                    }
+                    if process.config().schedule.is_some() {
+                        match process.next_run() {
+                            Some(next) => line.push_str(&format!(
+                                " next run at {}",
+                                LocalTime::from_timestamp(next)
+                            )),
+                            None => line.push_str(" no next run"),
+                        }
+                    }
+                    data.push_str(&line);
+                    data.push('\n');
                 }
                 send_data(&mut stream, data.as_bytes()).unwrap();
             }
             &ApiKind::Kill => {
                 info!("kill request from {}", addr);
                 for process in processes.iter() {
-                    process.read().unwrap().kill();
+                    stop(process);
                 }
             }
             &ApiKind::Log => {
@@ -94,13 +170,38 @@ pub fn handle_client(mut stream: TcpStream, processes: Arc<Vec<ProcessSync>>) {
                             continue;
                         }
                     };
-                    let mut holder = process.holder();
-                    holder.read_stdout();
+                    let holder = process.holder();
                     let readed = holder.get_stdout();
                     data.extend(readed.iter());
                 }
                 send_data(&mut stream, &data).unwrap();
             }
+            &ApiKind::LastRun => {
+                info!("last run request from {}", addr);
+                let targets: Vec<&str> = req.args().iter().map(|arg| arg.value()).collect();
+                let mut data = String::new();
+                for process in processes.iter() {
+                    let process = process.read().unwrap();
+                    let name = process.proc_name().to_owned();
+                    if !targets.is_empty()
+                        && !targets
+                            .iter()
+                            .any(|t| *t == name || *t == process.program_name())
+                    {
+                        continue;
+                    }
+                    match process.last_run() {
+                        Some(run) => data.push_str(&format!(
+                            "{} {:?} in {}\n",
+                            name,
+                            run.state,
+                            format_duration(run.duration)
+                        )),
+                        None => data.push_str(&format!("{} never ran\n", name)),
+                    }
+                }
+                send_data(&mut stream, data.as_bytes()).unwrap();
+            }
             &ApiKind::Foreground => {
                 let args = req.args();
                 let mut target = None;
@@ -113,10 +214,9 @@ pub fn handle_client(mut stream: TcpStream, processes: Arc<Vec<ProcessSync>>) {
                     Some(val) => {
                         let mut handled = false;
                         for process in processes.iter() {
-                            let prc = process.read().unwrap();
-                            if prc.proc_name() == val {
+                            if process.read().unwrap().proc_name() == val {
                                 info!("foreground request from {}", addr);
-                                handle_fg(&mut stream, prc);
+                                handle_fg(&mut stream, process.clone());
                                 info!("ended foreground from {}", addr);
                                 handled = true;
                             }
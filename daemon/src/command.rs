@@ -57,14 +57,17 @@ pub struct Command {
 }
 
 impl Command {
-    /// Create a new command
-    pub fn new(command_str: &str, mut env: Vec<String>) -> Command {
+    /// Create a new command, the daemon environment is inherited unless clean_env is set
+    pub fn new(command_str: &str, vars: &[(String, String)], clean_env: bool) -> Command {
         let splitted = split_ident(command_str);
         let path = resolve_path(&splitted[0]);
-        let mut envs = env::vars()
-            .map(|(n, v)| format!("{}={}", n, v))
-            .collect::<Vec<String>>();
-        envs.append(&mut env);
+        let envs = if clean_env {
+            Vec::new()
+        } else {
+            env::vars()
+                .map(|(n, v)| format!("{}={}", n, v))
+                .collect::<Vec<String>>()
+        };
         let splitted_c = splitted
             .into_iter()
             .filter_map(|s| CString::new(s).ok())
@@ -73,10 +76,23 @@ impl Command {
         let envs_c = envs.into_iter()
             .filter_map(|s| CString::new(s).ok())
             .collect();
-        Command {
+        let mut command = Command {
             path: path_c,
             args: splitted_c,
             env: envs_c,
+        };
+        for &(ref name, ref value) in vars {
+            command.set_env(name, value);
+        }
+        command
+    }
+
+    /// Set an environment variable, replacing any previous value
+    pub fn set_env(&mut self, name: &str, value: &str) {
+        let prefix = format!("{}=", name);
+        self.env.retain(|e| !e.as_bytes().starts_with(prefix.as_bytes()));
+        if let Ok(var) = CString::new(format!("{}{}", prefix, value)) {
+            self.env.push(var);
         }
     }
 
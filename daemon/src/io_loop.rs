@@ -0,0 +1,80 @@
+//! Event loop draining output of every process as soon as it is available
+
+use ProcessSync;
+
+use failure::Error;
+use nix::errno::Errno;
+use nix::poll::*;
+use nix::unistd::*;
+use self_pipe;
+use std::os::unix::io::RawFd;
+use std::sync::Arc;
+use std::thread;
+use taskmaster::ffi;
+
+/// Write end of the wake pipe
+static mut WAKE_FD: RawFd = -1;
+
+/// Wake up the loop so it watches file descriptors of new processes
+pub fn wake() {
+    unsafe {
+        if WAKE_FD >= 0 {
+            let _ = write(WAKE_FD, b"\0");
+        }
+    }
+}
+
+/// Start the loop in a dedicated thread
+pub fn start(processes: Arc<Vec<ProcessSync>>) -> Result<(), Error> {
+    let (p_read, p_write) = self_pipe::open()?;
+    ffi::set_nonblocking(p_read)?;
+    unsafe {
+        WAKE_FD = p_write;
+    }
+    thread::spawn(move || run(p_read, &processes));
+    Ok(())
+}
+
+fn run(wake_fd: RawFd, processes: &[ProcessSync]) {
+    loop {
+        let mut fds = vec![wake_fd];
+        for process in processes.iter() {
+            fds.extend(process.read().unwrap().holder().output_fds());
+        }
+        let mut poll_fds = fds
+            .iter()
+            .map(|fd| PollFd::new(*fd, POLLIN))
+            .collect::<Vec<PollFd>>();
+        match poll(&mut poll_fds, -1) {
+            Ok(_) => {}
+            Err(::nix::Error::Sys(Errno::EINTR)) => continue,
+            Err(e) => {
+                critical!("io loop stopped: {}", e);
+                return;
+            }
+        }
+        for (fd, poll_fd) in fds.iter().zip(poll_fds.iter()) {
+            match poll_fd.revents() {
+                Some(events) if !events.is_empty() => {}
+                _ => continue,
+            }
+            if *fd == wake_fd {
+                let mut buf = [0; 64];
+                while let Ok(size) = read(wake_fd, &mut buf) {
+                    if size == 0 {
+                        break;
+                    }
+                }
+                continue;
+            }
+            for process in processes.iter() {
+                let process = process.read().unwrap();
+                let mut holder = process.holder();
+                if holder.output_fds().contains(fd) {
+                    holder.drain(*fd);
+                    break;
+                }
+            }
+        }
+    }
+}
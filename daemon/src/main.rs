@@ -6,11 +6,23 @@ extern crate serde_yaml;
 #[macro_use]
 extern crate taskmaster;
 
+mod activation;
+mod cgroup;
+mod check;
 mod client;
 mod command;
+mod io_loop;
+mod notify;
 mod process;
+mod procfs;
+mod reaper;
+mod scheduler;
+mod self_pipe;
+mod shutdown;
+mod socket;
+mod usage;
 
-use failure::Error;
+use failure::{err_msg, Error};
 use nix::sys::stat::*;
 use nix::unistd::*;
 use process::*;
@@ -20,7 +32,7 @@ use std::process::exit;
 use std::sync::{Arc, RwLock};
 use std::thread;
 use taskmaster::config::*;
-use taskmaster::ffi::close_all_fd;
+use taskmaster::ffi::{self, close_all_fd, Resource};
 use taskmaster::log::*;
 
 type ProcessSync = Arc<RwLock<Process>>;
@@ -47,9 +59,30 @@ fn get_config() -> Result<Config, Error> {
     serde_yaml::from_reader(&mut f).map_err(|e| e.into())
 }
 
+/// Raise the fd limit of the daemon to at least minfds
+fn raise_nofile(minfds: u64) -> Result<(), Error> {
+    let (soft, hard) = ffi::getrlimit(Resource::Nofile)?;
+    if soft >= minfds {
+        return Ok(());
+    }
+    match ffi::setrlimit(Resource::Nofile, minfds, hard.max(minfds)) {
+        Ok(_) => {
+            info!("raised fd limit from {} to {}", soft, minfds);
+            Ok(())
+        }
+        Err(e) => Err(err_msg(format!(
+            "cannot raise fd limit from {} to minfds {} (hard limit {}): {}",
+            soft, minfds, hard, e
+        ))),
+    }
+}
+
 fn main_wrapper() -> Result<(), Error> {
     let log_path = ::std::env::current_dir()?.join("log");
     let config = get_config()?;
+    config.check_dependencies()?;
+    let daemon_config = config.daemon().cloned().unwrap_or(DaemonConfig::default());
+    raise_nofile(daemon_config.minfds.max(0) as u64)?;
     trace!("config:\n{:#?}", config);
     trace!("yaml: {}", serde_yaml::to_string(&config).unwrap());
     daemonize();
@@ -74,19 +107,47 @@ fn main_wrapper() -> Result<(), Error> {
             ).unwrap(),
         )
     });
+    // the attribute is not inherited by fork, set it once daemonized
+    match ffi::set_child_subreaper() {
+        Ok(_) => debug!("daemon is a child subreaper"),
+        Err(e) => {
+            warn!("cannot become child subreaper, orphaned processes are not tracked");
+            trace!("error: {}", e);
+        }
+    }
+    let cgroup_parent = cgroup::init(&daemon_config.cgroup_parent);
+    let notify_dir = notify::init(&daemon_config.notify_dir);
     let mut processes = Vec::new();
     for process in config.processes() {
-        let p = process.clone();
-        processes.push(Arc::new(RwLock::new(Process::new(p))));
+        let sockets = socket::bind_all(&process.sockets, &process.name).map_err(|e| {
+            err_msg(format!(
+                "cannot bind sockets of program {}: {}",
+                process.name, e
+            ))
+        })?;
+        let sockets = Arc::new(sockets);
+        for p in process.instances() {
+            let process = Process::new(
+                p,
+                cgroup_parent.as_ref().map(|p| p.as_path()),
+                sockets.clone(),
+                notify_dir.as_ref().map(|p| p.as_path()),
+            );
+            processes.push(Arc::new(RwLock::new(process)));
+        }
     }
     let processes = Arc::new(processes);
+    reaper::start(processes.clone())?;
+    shutdown::start(processes.clone())?;
+    io_loop::start(processes.clone())?;
+    usage::start(processes.clone());
+    scheduler::start(processes.clone());
+    activation::start(processes.clone());
+    notify::start(processes.clone());
     blather!("spawning processes");
-    for process in processes.iter() {
-        let process = process.clone();
-        thread::spawn(move || {
-            process.write().unwrap().spawn();
-            process.read().unwrap().track_state();
-        });
+    {
+        let processes = processes.clone();
+        thread::spawn(move || start_processes(&processes));
     }
     info!("starting listener");
     let listener = TcpListener::bind(("127.0.0.1", taskmaster::DEFAULT_PORT))?;
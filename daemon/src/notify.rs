@@ -0,0 +1,285 @@
+//! sd_notify protocol, processes report readiness, status and watchdog pings on a datagram socket
+
+use nix::errno::Errno;
+use nix::poll::*;
+use nix::unistd::{chown, geteuid, Pid};
+use process::{restart, ProcessState};
+use socket;
+use std::fs::{self, DirBuilder, Permissions};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use taskmaster::ffi::{self, User};
+use ProcessSync;
+
+/// Delay between two checks of watchdogs in millis
+const TICK_MS: i32 = 1000;
+
+/// What a process reported on its notify socket since it was spawned
+#[derive(Clone, Debug)]
+pub struct NotifyState {
+    /// READY=1 was received
+    pub ready: bool,
+    /// Text of the last STATUS=
+    pub status: Option<String>,
+    /// Pid given by MAINPID=
+    pub main_pid: Option<Pid>,
+    /// STOPPING=1 was received
+    pub stopping: bool,
+    /// Last WATCHDOG=1, spawn time until the first one
+    pub watchdog_at: Instant,
+}
+
+impl NotifyState {
+    /// State of a freshly spawned process
+    pub fn new() -> NotifyState {
+        NotifyState {
+            ready: false,
+            status: None,
+            main_pid: None,
+            stopping: false,
+            watchdog_at: Instant::now(),
+        }
+    }
+}
+
+/// Create directory of notify sockets, None if it cannot be created or is not safe
+pub fn init(dir: &Path) -> Option<PathBuf> {
+    match create_private_dir(dir) {
+        Ok(_) => Some(dir.to_path_buf()),
+        Err(e) => {
+            warn!(
+                "cannot use notify directory {}, NOTIFY_SOCKET disabled",
+                dir.display()
+            );
+            trace!("error: {}", e);
+            None
+        }
+    }
+}
+
+/// Create dir only the daemon can write to, refuse an existing one owned by someone else
+///
+/// Users of programs must still reach their socket, so the directory can be listed by
+/// everyone while each socket is only accessible to its owner.
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    if let Some(parent) = dir.parent() {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o755)
+            .create(parent)?;
+    }
+    match DirBuilder::new().mode(0o755).create(dir) {
+        Ok(_) => {}
+        Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e),
+    }
+    let meta = fs::symlink_metadata(dir)?;
+    if !meta.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "not a directory",
+        ));
+    }
+    if meta.uid() != geteuid().into() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("owned by uid {}, not by the daemon", meta.uid()),
+        ));
+    }
+    if meta.mode() & 0o022 != 0 {
+        fs::set_permissions(dir, Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+/// Datagram socket a process sends notifications to
+#[derive(Debug)]
+pub struct NotifySocket {
+    socket: UnixDatagram,
+    path: PathBuf,
+}
+
+impl NotifySocket {
+    /// Bind socket of process, owned by the user it runs as
+    pub fn bind(dir: &Path, name: &str, user: Option<&User>) -> Result<NotifySocket, String> {
+        let path = dir.join(format!("{}.sock", name));
+        socket::remove_stale(&path);
+        let socket = UnixDatagram::bind(&path)
+            .map_err(|e| format!("cannot bind {}: {}", path.display(), e))?;
+        socket
+            .set_nonblocking(true)
+            .map_err(|e| format!("cannot set {} non blocking: {}", path.display(), e))?;
+        ffi::set_pass_cred(socket.as_raw_fd())
+            .map_err(|e| format!("cannot receive credentials on {}: {}", path.display(), e))?;
+        fs::set_permissions(&path, Permissions::from_mode(0o600))
+            .map_err(|e| format!("cannot set mode of {}: {}", path.display(), e))?;
+        if let Some(user) = user {
+            chown(&path, Some(user.uid), Some(user.gid))
+                .map_err(|e| format!("cannot set owner of {}: {}", path.display(), e))?;
+        }
+        Ok(NotifySocket {
+            socket: socket,
+            path: path,
+        })
+    }
+
+    /// Path exported as NOTIFY_SOCKET
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+
+    /// Receive every pending message with the pid of its sender
+    fn recv_all(&self) -> Vec<(String, Option<Pid>)> {
+        let mut messages = Vec::new();
+        let mut buf = [0; 4096];
+        while let Ok((size, sender)) = ffi::recv_with_pid(self.fd(), &mut buf) {
+            messages.push((String::from_utf8_lossy(&buf[0..size]).into_owned(), sender));
+        }
+        messages
+    }
+}
+
+impl Drop for NotifySocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Start receiving notifications of processes having a notify socket
+pub fn start(processes: Arc<Vec<ProcessSync>>) {
+    let notified: Vec<ProcessSync> = processes
+        .iter()
+        .filter(|p| p.read().unwrap().notify_socket().is_some())
+        .cloned()
+        .collect();
+    if notified.is_empty() {
+        return;
+    }
+    thread::spawn(move || run(&notified));
+}
+
+fn run(processes: &[ProcessSync]) {
+    let fds = processes
+        .iter()
+        .map(|p| p.read().unwrap().notify_socket().unwrap().fd())
+        .collect::<Vec<RawFd>>();
+    loop {
+        let mut poll_fds = fds
+            .iter()
+            .map(|fd| PollFd::new(*fd, POLLIN))
+            .collect::<Vec<PollFd>>();
+        match poll(&mut poll_fds, TICK_MS) {
+            Ok(_) => {}
+            Err(::nix::Error::Sys(Errno::EINTR)) => continue,
+            Err(e) => {
+                critical!("notify loop stopped: {}", e);
+                return;
+            }
+        }
+        for (process, poll_fd) in processes.iter().zip(poll_fds.iter()) {
+            match poll_fd.revents() {
+                Some(events) if !events.is_empty() => {}
+                _ => continue,
+            }
+            let messages = process.read().unwrap().notify_socket().unwrap().recv_all();
+            for (message, sender) in messages {
+                let allowed = match sender {
+                    Some(pid) => process.read().unwrap().is_notify_sender(pid),
+                    None => false,
+                };
+                if !allowed {
+                    warn!(
+                        "ignored notification of process {} from pid {:?}",
+                        process.read().unwrap().proc_name(),
+                        sender
+                    );
+                    continue;
+                }
+                handle_message(process, &message);
+            }
+        }
+        for process in processes.iter() {
+            check_watchdog(process);
+        }
+    }
+}
+
+/// Apply every `KEY=value` line of a notification
+fn handle_message(process: &ProcessSync, message: &str) {
+    for line in message.lines() {
+        let idx = match line.find('=') {
+            Some(idx) => idx,
+            None => continue,
+        };
+        let mut trigger = false;
+        {
+            let process = process.read().unwrap();
+            let name = process.proc_name();
+            let mut state = process.notify_state();
+            match (&line[..idx], &line[idx + 1..]) {
+                ("READY", "1") => {
+                    debug!("process {} notified ready", name);
+                    state.ready = true;
+                }
+                ("STATUS", status) => state.status = Some(status.to_owned()),
+                ("MAINPID", pid) => match pid.parse() {
+                    Ok(pid) => state.main_pid = Some(Pid::from_raw(pid)),
+                    Err(_) => warn!("process {} notified invalid MAINPID {}", name, pid),
+                },
+                ("STOPPING", "1") => {
+                    info!("process {} is stopping", name);
+                    state.stopping = true;
+                }
+                ("WATCHDOG", "1") => state.watchdog_at = Instant::now(),
+                ("WATCHDOG", "trigger") => {
+                    warn!("process {} triggered its watchdog, restarting", name);
+                    state.watchdog_at = Instant::now();
+                    trigger = true;
+                }
+                _ => blather!("ignored notification `{}` of process {}", line, name),
+            }
+        }
+        if trigger {
+            let process = process.clone();
+            thread::spawn(move || restart(&process));
+        }
+    }
+}
+
+/// Restart a running process that sent no watchdog ping in time
+fn check_watchdog(process: &ProcessSync) {
+    {
+        let process = process.read().unwrap();
+        let secs = match process.config().watchdog_secs {
+            Some(secs) => secs,
+            None => return,
+        };
+        let state = process.get_state().clone();
+        match state {
+            ProcessState::Running(_) => {}
+            _ => return,
+        }
+        let mut notify = process.notify_state();
+        if notify.stopping || notify.watchdog_at.elapsed() < Duration::from_secs(secs) {
+            return;
+        }
+        notify.watchdog_at = Instant::now();
+        warn!(
+            "process {} missed its watchdog of {}s, restarting",
+            process.proc_name(),
+            secs
+        );
+    }
+    let process = process.clone();
+    thread::spawn(move || restart(&process));
+}
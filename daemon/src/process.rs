@@ -1,40 +1,122 @@
 //! Process module
 
+use cgroup::Cgroup;
+use check;
 use command::Command;
+use io_loop;
+use nix::errno::Errno;
 use nix::fcntl;
-use nix::sys::{stat, wait};
+use nix::pty;
+use nix::sys::signal::Signal;
+use nix::sys::{stat, termios, wait};
 use nix::unistd::*;
+use notify::{NotifySocket, NotifyState};
+use procfs;
+use reaper;
+use shutdown::is_shutting_down;
+use socket::{self, ListenSocket};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
 use std::os::unix::io::*;
-use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, RwLock, RwLockReadGuard};
+use std::thread;
+use std::time::{Duration, Instant};
 use taskmaster::config::*;
+use taskmaster::ffi::{self, User};
+use taskmaster::signal::StopSignal;
+use ProcessSync;
 
 /// Get process state
 #[derive(Clone, Debug, PartialEq)]
 pub enum ProcessState {
-    /// Starting process
-    Starting,
+    /// Started but not yet up for start_secs, param is pid
+    Starting(Pid),
     /// In running state, param is pid
     Running(Pid),
     /// Fail start
     Backoff,
-    /// Stopping process
-    Stopping,
+    /// Stopping process, param is pid
+    Stopping(Pid),
     /// Process manually stopped
     Stopped,
     /// Exited, param is exit code
     Exited(i8),
+    /// Killed by a signal that was not sent by us, param is signal
+    Killed(Signal),
     /// Fail start a lot of time
     Fatal,
+    /// Waiting for dependencies to run, param is dependencies
+    Waiting(Vec<String>),
+    /// Oneshot exited with an expected code
+    Completed,
 }
 
-// TODO: use signal to handle process
-#[derive(Clone, Debug, PartialEq)]
+/// Hold pipes of a process and dispatch its output
+#[derive(Debug)]
 pub struct ProcessHolder {
     stdin: Option<RawFd>,
     stdout: Option<RawFd>,
     stderr: Option<RawFd>,
     stdout_readed: Vec<u8>,
     stderr_readed: Vec<u8>,
+    stdout_log: Option<File>,
+    stderr_log: Option<File>,
+    stdout_maxbytes: usize,
+    stderr_maxbytes: usize,
+    subscribers: Vec<Sender<Vec<u8>>>,
+    pty: bool,
+}
+
+fn set_nonblocking(fd: RawFd) {
+    if let Err(e) = ffi::set_nonblocking(fd) {
+        trace!("error with fcntl: {}", e);
+    }
+}
+
+fn open_log(log: &OutputLog) -> Option<File> {
+    match log {
+        &OutputLog::File(ref path) => {
+            match OpenOptions::new().create(true).append(true).open(path) {
+                Ok(f) => Some(f),
+                Err(e) => {
+                    warn!("cannot open log file {}", path.display());
+                    trace!("error: {}", e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Open a pseudo-terminal laid out as the stdin and stdout pipes it replaces
+fn open_pty() -> ::nix::Result<((RawFd, RawFd), (RawFd, RawFd))> {
+    let pty = pty::openpty(None::<&pty::Winsize>, None::<&termios::Termios>)?;
+    let fds = dup(pty.master).and_then(|master| dup(pty.slave).map(|slave| (master, slave)));
+    let (master, slave) = match fds {
+        Ok(fds) => fds,
+        Err(e) => {
+            let _ = close(pty.master);
+            let _ = close(pty.slave);
+            return Err(e);
+        }
+    };
+    for fd in &[pty.master, pty.slave, master, slave] {
+        let _ = ffi::set_cloexec(*fd);
+    }
+    Ok(((pty.slave, pty.master), (master, slave)))
+}
+
+/// Append data to buf, dropping the oldest bytes past maxbytes
+fn keep_tail(buf: &mut Vec<u8>, data: &[u8], maxbytes: usize) {
+    buf.extend_from_slice(data);
+    if buf.len() > maxbytes {
+        let excess = buf.len() - maxbytes;
+        buf.drain(..excess);
+    }
 }
 
 impl ProcessHolder {
@@ -46,6 +128,12 @@ impl ProcessHolder {
             stderr: None,
             stdout_readed: Vec::new(),
             stderr_readed: Vec::new(),
+            stdout_log: None,
+            stderr_log: None,
+            stdout_maxbytes: 0,
+            stderr_maxbytes: 0,
+            subscribers: Vec::new(),
+            pty: false,
         }
     }
 
@@ -57,36 +145,51 @@ impl ProcessHolder {
 
     /// Set stdout
     pub fn stdout(mut self, stdout: RawFd) -> ProcessHolder {
-        match fcntl::fcntl(stdout, fcntl::FcntlArg::F_GETFL) {
-            Ok(f) => match fcntl::fcntl(
-                stdout,
-                fcntl::FcntlArg::F_SETFL(fcntl::OFlag::from_bits_truncate(f) | fcntl::O_NONBLOCK),
-            ) {
-                Ok(_) => {}
-                Err(e) => trace!("error with fcntl: {}", e),
-            },
-            Err(_) => {}
-        };
+        set_nonblocking(stdout);
         self.stdout = Some(stdout);
         self
     }
 
     /// Set stderr
     pub fn stderr(mut self, stderr: RawFd) -> ProcessHolder {
-        match fcntl::fcntl(stderr, fcntl::FcntlArg::F_GETFL) {
-            Ok(f) => match fcntl::fcntl(
-                stderr,
-                fcntl::FcntlArg::F_SETFL(fcntl::OFlag::from_bits_truncate(f) | fcntl::O_NONBLOCK),
-            ) {
-                Ok(_) => {}
-                Err(e) => trace!("error with fcntl: {}", e),
-            },
-            Err(_) => {}
-        };
+        set_nonblocking(stderr);
         self.stderr = Some(stderr);
         self
     }
 
+    /// Set stdout log file, only the last maxbytes of output are kept in memory
+    pub fn stdout_log(mut self, log: &OutputLog, maxbytes: usize) -> ProcessHolder {
+        self.stdout_log = open_log(log);
+        self.stdout_maxbytes = maxbytes;
+        self
+    }
+
+    /// Set stderr log file, only the last maxbytes of output are kept in memory
+    pub fn stderr_log(mut self, log: &OutputLog, maxbytes: usize) -> ProcessHolder {
+        self.stderr_log = open_log(log);
+        self.stderr_maxbytes = maxbytes;
+        self
+    }
+
+    /// Mark stdin as the master of a pseudo-terminal
+    pub fn pty(mut self) -> ProcessHolder {
+        self.pty = true;
+        self
+    }
+
+    /// Is process attached to a pseudo-terminal
+    pub fn is_pty(&self) -> bool {
+        self.pty
+    }
+
+    /// Set window size of the pseudo-terminal, nothing is done without one
+    pub fn resize(&self, rows: u16, cols: u16) -> ::nix::Result<()> {
+        match self.stdin {
+            Some(fd) if self.pty => ffi::set_winsize(fd, rows, cols),
+            _ => Ok(()),
+        }
+    }
+
     /// Get stdout
     pub fn get_stdout(&self) -> &Vec<u8> {
         &self.stdout_readed
@@ -97,51 +200,67 @@ impl ProcessHolder {
         &self.stderr_readed
     }
 
-    /// Write to stdin
-    pub fn write_stdin(&self, buf: &[u8]) -> ::nix::Result<usize> {
-        if let Some(fd) = self.stdin {
-            write(fd, buf)
-        } else {
-            Ok(0)
-        }
+    /// Get a copy of stdin, written to without holding the holder and closed by the caller
+    pub fn dup_stdin(&self) -> Option<RawFd> {
+        let fd = self.stdin?;
+        fcntl::fcntl(fd, fcntl::FcntlArg::F_DUPFD_CLOEXEC(0)).ok()
     }
 
-    /// Read to stdout
-    pub fn read_stdout(&mut self) -> Vec<u8> {
-        let mut readed = Vec::new();
-        if let Some(fd) = self.stdout {
-            let mut buf = [0; 1024];
-            while let Ok(size) = read(fd, &mut buf) {
-                if size == 0 {
+    /// Send stdout to sender as it is read
+    pub fn subscribe(&mut self, sender: Sender<Vec<u8>>) {
+        self.subscribers.push(sender);
+    }
+
+    /// Get output file descriptors that are still open
+    pub fn output_fds(&self) -> Vec<RawFd> {
+        self.stdout
+            .iter()
+            .chain(self.stderr.iter())
+            .cloned()
+            .collect()
+    }
+
+    /// Read everything available on fd and dispatch it, close fd on end of file
+    pub fn drain(&mut self, fd: RawFd) {
+        let is_stdout = self.stdout == Some(fd);
+        let mut buf = [0; 4096];
+        loop {
+            match read(fd, &mut buf) {
+                Ok(0) => break,
+                Ok(size) => {
+                    blather!("read {} bytes on fd {}", size, fd);
+                    self.dispatch(is_stdout, &buf[0..size]);
+                }
+                Err(::nix::Error::Sys(Errno::EAGAIN)) => return,
+                Err(::nix::Error::Sys(Errno::EINTR)) => {}
+                Err(e) => {
+                    trace!("error reading fd {}: {}", fd, e);
                     break;
                 }
-                blather!("stdout read: {}", size);
-                self.stdout_readed.extend(buf.iter());
-                readed.extend(buf.iter());
-                buf = [0; 1024];
             }
         }
-        readed
+        let _ = close(fd);
+        if is_stdout {
+            self.stdout = None;
+        } else {
+            self.stderr = None;
+        }
     }
 
-    /// Read to stderr
-    pub fn read_stderr(&mut self) -> Vec<u8> {
-        let mut readed = Vec::new();
-        blather!("started reading stderr");
-        if let Some(fd) = self.stderr {
-            let mut buf = [0; 1024];
-            while let Ok(size) = read(fd, &mut buf) {
-                blather!("stderr read: {}", size);
-                if size == 0 {
-                    break;
-                }
-                self.stderr_readed.extend(buf.iter());
-                readed.extend(buf.iter());
-                buf = [0; 1024];
+    fn dispatch(&mut self, is_stdout: bool, data: &[u8]) {
+        if is_stdout {
+            keep_tail(&mut self.stdout_readed, data, self.stdout_maxbytes);
+            if let Some(ref mut log) = self.stdout_log {
+                let _ = log.write_all(data);
+            }
+            self.subscribers
+                .retain(|sub| sub.send(data.to_vec()).is_ok());
+        } else {
+            keep_tail(&mut self.stderr_readed, data, self.stderr_maxbytes);
+            if let Some(ref mut log) = self.stderr_log {
+                let _ = log.write_all(data);
             }
         }
-        blather!("ended reading stderr");
-        readed
     }
 }
 
@@ -159,55 +278,341 @@ impl Drop for ProcessHolder {
     }
 }
 
+/// Result of the health checks of a running process
+#[derive(Clone, Debug, PartialEq)]
+pub enum Health {
+    /// No check passed nor failed yet
+    Unknown,
+    /// Last check passed
+    Healthy,
+    /// Number of consecutive failed checks
+    Failing(u32),
+}
+
+/// Outcome of a finished run
+#[derive(Clone, Debug)]
+pub struct RunResult {
+    /// State the run ended in
+    pub state: ProcessState,
+    /// Time between spawn and exit
+    pub duration: Duration,
+}
+
+/// Format duration as secs with millis
+pub fn format_duration(duration: Duration) -> String {
+    format!(
+        "{}.{:03}s",
+        duration.as_secs(),
+        duration.subsec_nanos() / 1_000_000
+    )
+}
+
+/// Time a killed helper gets to be reported by the reaper, in secs
+const HELPER_REAP_SECS: u64 = 5;
+
+/// Wait for the status of a helper, it is killed when timeout is reached
+pub fn wait_helper(
+    pid: Pid,
+    receiver: &Receiver<wait::WaitStatus>,
+    timeout: Option<Duration>,
+) -> Result<wait::WaitStatus, String> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return receiver.recv().map_err(|e| e.to_string()),
+    };
+    match receiver.recv_timeout(timeout) {
+        Ok(status) => Ok(status),
+        Err(_) => {
+            let _ = StopSignal::Kill.kill(pid, true);
+            let _ = receiver.recv_timeout(Duration::from_secs(HELPER_REAP_SECS));
+            Err(format!("timed out after {}s", timeout.as_secs()))
+        }
+    }
+}
+
 /// Process handler
 #[derive(Debug)]
 pub struct Process {
-    command: Command,
     state: RwLock<ProcessState>,
     config: ProcessConfig,
     count_fail: u8,
     holder: Mutex<ProcessHolder>,
+    exit_status: Mutex<Option<wait::WaitStatus>>,
+    exited: Condvar,
+    helpers: Mutex<Vec<(Pid, Sender<wait::WaitStatus>)>>,
+    group: Option<Pid>,
+    orphans: Mutex<Vec<Pid>>,
+    overlaps: Mutex<Vec<Pid>>,
+    health: Mutex<Health>,
+    restart: AtomicBool,
+    supervised: AtomicBool,
+    cgroup: Option<Cgroup>,
+    sockets: Arc<Vec<ListenSocket>>,
+    notify_socket: Option<NotifySocket>,
+    notify: Mutex<NotifyState>,
+    oom_kills: u64,
+    spawned_at: Option<Instant>,
+    last_run: Mutex<Option<RunResult>>,
+    next_run: Mutex<Option<i64>>,
 }
 
 impl Process {
     /// Create a new process
-    pub fn new(config: ProcessConfig) -> Process {
+    pub fn new(
+        config: ProcessConfig,
+        cgroup_parent: Option<&Path>,
+        sockets: Arc<Vec<ListenSocket>>,
+        notify_dir: Option<&Path>,
+    ) -> Process {
+        let has_limits =
+            config.memory_max.is_some() || config.cpu_max.is_some() || config.pids_max.is_some();
+        if cgroup_parent.is_none() && has_limits {
+            warn!(
+                "cgroups are disabled, limits of process {} are ignored",
+                config.name
+            );
+        }
+        let user = config.user.as_ref().and_then(|name| User::lookup(name));
+        let notify_socket =
+            notify_dir.and_then(
+                |dir| match NotifySocket::bind(dir, &config.name, user.as_ref()) {
+                    Ok(socket) => Some(socket),
+                    Err(e) => {
+                        warn!("cannot create notify socket of process {}", config.name);
+                        trace!("error: {}", e);
+                        None
+                    }
+                },
+            );
         Process {
-            command: Command::new(&config.command, config.envs.clone().unwrap_or(Vec::new())),
+            cgroup: cgroup_parent.map(|parent| Cgroup::new(parent, &config.name)),
+            sockets: sockets,
+            notify_socket: notify_socket,
+            notify: Mutex::new(NotifyState::new()),
+            oom_kills: 0,
+            spawned_at: None,
+            last_run: Mutex::new(None),
+            next_run: Mutex::new(None),
             state: RwLock::new(ProcessState::Stopped),
             config: config,
             count_fail: 0,
             holder: Mutex::new(ProcessHolder::new()),
+            exit_status: Mutex::new(None),
+            exited: Condvar::new(),
+            helpers: Mutex::new(Vec::new()),
+            group: None,
+            orphans: Mutex::new(Vec::new()),
+            overlaps: Mutex::new(Vec::new()),
+            health: Mutex::new(Health::Unknown),
+            restart: AtomicBool::new(false),
+            supervised: AtomicBool::new(false),
         }
     }
 
+    /// Get config
+    pub fn config(&self) -> &ProcessConfig {
+        &self.config
+    }
+
     /// Get proc name
     pub fn proc_name(&self) -> &str {
         &self.config.name
     }
 
+    /// Get priority
+    pub fn priority(&self) -> u16 {
+        self.config.priority
+    }
+
+    /// Check if process is done starting, whether it succeeded or not
+    pub fn is_settled(&self) -> bool {
+        match *self.state.read().unwrap() {
+            ProcessState::Starting(_) | ProcessState::Backoff => false,
+            _ => true,
+        }
+    }
+
+    /// Is process started by its schedule or its sockets rather than with the daemon
+    pub fn is_triggered(&self) -> bool {
+        self.config.schedule.is_some() || self.config.start_on == StartOn::Socket
+    }
+
+    /// Is process down with no supervisor to restart it, a connection to its sockets would start it
+    pub fn is_inactive(&self) -> bool {
+        if self.is_supervised() {
+            return false;
+        }
+        match *self.state.read().unwrap() {
+            ProcessState::Stopped
+            | ProcessState::Exited(_)
+            | ProcessState::Killed(_)
+            | ProcessState::Completed
+            | ProcessState::Fatal => true,
+            _ => false,
+        }
+    }
+
+    /// Check if a supervisor owns the process, it may still restart it
+    pub fn is_supervised(&self) -> bool {
+        self.supervised.load(Ordering::SeqCst)
+    }
+
+    /// Make the caller the supervisor of the process, false if one already owns it
+    pub fn claim_supervisor(&self) -> bool {
+        !self.supervised.swap(true, Ordering::SeqCst)
+    }
+
+    /// Get pids of the process, of its overlapping runs and of its descendants, by process
+    /// group and cgroup
+    pub fn members(&self) -> Vec<Pid> {
+        let mut pids = match self.group {
+            Some(group) if self.pid().is_some() => procfs::group_members(group),
+            _ => Vec::new(),
+        };
+        let mut others = self.orphans();
+        others.extend(self.overlaps());
+        if let Some(ref cgroup) = self.cgroup {
+            others.extend(cgroup.procs());
+        }
+        for pid in others {
+            if !pids.contains(&pid) {
+                pids.push(pid);
+            }
+        }
+        pids
+    }
+
+    /// Get sockets bound for the process
+    pub fn sockets(&self) -> &[ListenSocket] {
+        &self.sockets
+    }
+
+    /// Get name of the program this process is an instance of
+    pub fn program_name(&self) -> &str {
+        if self.config.num_procs > 1 {
+            if let Some(idx) = self.config.name.rfind(':') {
+                return &self.config.name[..idx];
+            }
+        }
+        &self.config.name
+    }
+
+    /// Get programs this process depends on
+    pub fn depends_on(&self) -> &Vec<String> {
+        &self.config.depends_on
+    }
+
+    /// Check if process satisfies processes depending on it, oneshots must be completed
+    pub fn is_ready(&self) -> bool {
+        match *self.state.read().unwrap() {
+            ProcessState::Running(_) => !self.is_oneshot(),
+            ProcessState::Completed => true,
+            _ => false,
+        }
+    }
+
+    /// Check if process is still running or starting, so it may become ready
+    fn is_pending(&self) -> bool {
+        self.pid().is_some() || !self.is_settled()
+    }
+
+    /// Check if process is a oneshot
+    pub fn is_oneshot(&self) -> bool {
+        self.config.kind == ProgramKind::Oneshot
+    }
+
+    /// Get notify socket of the process
+    pub fn notify_socket(&self) -> Option<&NotifySocket> {
+        self.notify_socket.as_ref()
+    }
+
+    /// Check if pid may notify for the process, its pid, MAINPID or one of its group
+    pub fn is_notify_sender(&self, sender: Pid) -> bool {
+        if self.pid() == Some(sender) || self.notify_state().main_pid == Some(sender) {
+            return true;
+        }
+        match self.group {
+            Some(group) => getpgid(Some(sender)).ok() == Some(group),
+            None => false,
+        }
+    }
+
+    /// Get what the process notified since it was spawned
+    pub fn notify_state(&self) -> MutexGuard<NotifyState> {
+        self.notify.lock().unwrap()
+    }
+
+    /// Check if exit code is one of exit_codes
+    fn is_expected_exit(&self, code: i8) -> bool {
+        self.config.exit_codes.contains(&(code as u8 as i32))
+    }
+
+    /// Mark process as waiting for its dependencies
+    pub fn set_waiting(&self) {
+        let mut state_lock = self.state.write().unwrap();
+        *state_lock = ProcessState::Waiting(self.config.depends_on.clone());
+    }
+
+    /// Give up starting process
+    pub fn abort_start(&self, reason: &str) {
+        error!("process {} not started: {}", self.config.name, reason);
+        let mut state_lock = self.state.write().unwrap();
+        *state_lock = ProcessState::Fatal;
+    }
+
+    /// Count a failed start, process is fatal when it reaches start_retries
     fn handle_fail(&mut self) {
         self.count_fail += 1;
         let mut state_lock = self.state.write().unwrap();
         if self.count_fail < self.config.start_retries {
             *state_lock = ProcessState::Backoff;
         } else {
+            error!(
+                "process {} failed to start {} times, giving up",
+                self.config.name, self.count_fail
+            );
             *state_lock = ProcessState::Fatal;
         }
-        ::std::process::exit(1);
     }
 
-    pub fn kill(&self) {
-        let state = self.state.read().unwrap().clone();
-        let pid = match state {
-            ProcessState::Running(pid) => pid,
-            _ => return,
-        };
-        drop(state);
+    /// Delay before the next start attempt, grows with each failure
+    fn backoff_delay(&self) -> Duration {
+        Duration::from_secs(self.count_fail as u64)
+    }
+
+    /// Secs a process must stay up to be running, oneshots and scheduled runs may exit at any time
+    fn start_secs(&self) -> u64 {
+        if self.is_oneshot() || self.config.schedule.is_some() {
+            return 0;
+        }
+        self.config.start_secs
+    }
+
+    /// Move from starting to running if pid is still alive after start_secs
+    fn confirm_start(&self, pid: Pid) {
         let mut state_lock = self.state.write().unwrap();
-        *state_lock = ProcessState::Stopping;
-        drop(state_lock);
-        match self.config.stop_signal.kill(pid) {
+        if *state_lock == ProcessState::Starting(pid) {
+            if self.config.readiness.is_some() || self.config.kind == ProgramKind::Notify {
+                info!("process {} is ready", self.config.name);
+            } else {
+                info!(
+                    "process {} is running after {}s",
+                    self.config.name,
+                    self.start_secs()
+                );
+            }
+            *state_lock = ProcessState::Running(pid);
+        }
+    }
+
+    /// Kill pid if it is still starting, its exit is then handled as a failed start
+    fn fail_start(&self, pid: Pid) {
+        if *self.state.read().unwrap() != ProcessState::Starting(pid) {
+            return;
+        }
+        warn!("process {} did not become ready in time", self.config.name);
+        let group = self.config.stop_as_group || self.config.kill_as_group;
+        match StopSignal::Kill.kill(pid, group) {
             Ok(_) => {}
             Err(e) => {
                 error!("killing pid {} failed", pid);
@@ -216,42 +621,446 @@ impl Process {
         }
     }
 
-    pub fn track_state(&self) {
-        let state = self.state.read().unwrap().clone();
-        let pid = match state {
-            ProcessState::Running(pid) => pid,
-            _ => return,
+    /// Send SIGKILL to pid still stopping at the end of stop_wait_secs
+    fn escalate_stop(&self, pid: Pid) {
+        warn!(
+            "process {} still alive after {}s, sending SIGKILL",
+            self.config.name, self.config.stop_wait_secs
+        );
+        let group = self.config.stop_as_group || self.config.kill_as_group;
+        match StopSignal::Kill.kill(pid, group) {
+            Ok(_) => {}
+            Err(e) => {
+                error!("killing pid {} failed", pid);
+                trace!("error: {}", e);
+            }
+        }
+        if let Some(ref cgroup) = self.cgroup {
+            cgroup.kill_all();
+        }
+    }
+
+    /// Get health of the process, None if it has no health check
+    pub fn health(&self) -> Option<Health> {
+        match self.config.healthcheck {
+            Some(_) => Some(self.health.lock().unwrap().clone()),
+            None => None,
+        }
+    }
+
+    /// Record a failed health check, return the number of consecutive failures
+    fn health_failed(&self) -> u32 {
+        let mut health = self.health.lock().unwrap();
+        let count = match *health {
+            Health::Failing(count) => count + 1,
+            _ => 1,
         };
-        drop(state);
+        *health = Health::Failing(count);
+        count
+    }
+
+    /// Wait until the reaper reports the exit of the process and update state
+    pub fn track_state(&self) -> Option<wait::WaitStatus> {
+        if self.pid().is_none() {
+            return None;
+        }
         trace!("tracking state");
-        loop {
-            match wait::waitpid(pid, None) {
-                Ok(wait::WaitStatus::Exited(_, status)) => {
-                    info!("process {} exited with code {}", self.config.name, status);
-                    let mut state_lock = self.state.write().unwrap();
-                    *state_lock = ProcessState::Exited(status);
-                    drop(state_lock);
-                    break;
+        let mut exit_lock = self.exit_status.lock().unwrap();
+        while exit_lock.is_none() {
+            exit_lock = self.exited.wait(exit_lock).unwrap();
+        }
+        let status = exit_lock.take().unwrap();
+        drop(exit_lock);
+        // descendants are reparented before the exit is reported, adopt them now, those
+        // left in the cgroup are only killed by an explicit stop
+        self.adopt_orphans(&reaper::daemon_children());
+        if let Some(ref cgroup) = self.cgroup {
+            if cgroup.oom_kills() > self.oom_kills {
+                warn!("process {} was killed by the oom killer", self.config.name);
+            }
+        }
+        match status {
+            wait::WaitStatus::Exited(_, status) => {
+                info!("process {} exited with code {}", self.config.name, status);
+                let mut state_lock = self.state.write().unwrap();
+                match *state_lock {
+                    ProcessState::Stopping(_) => *state_lock = ProcessState::Stopped,
+                    ProcessState::Starting(_) => {
+                        warn!("process {} exited too quickly", self.config.name);
+                        *state_lock = ProcessState::Backoff;
+                    }
+                    _ if self.is_oneshot() => {
+                        if self.is_expected_exit(status) {
+                            info!("process {} completed", self.config.name);
+                            *state_lock = ProcessState::Completed;
+                        } else {
+                            warn!("oneshot process {} failed", self.config.name);
+                            *state_lock = ProcessState::Backoff;
+                        }
+                    }
+                    _ => *state_lock = ProcessState::Exited(status),
                 }
-                Ok(wait::WaitStatus::Signaled(_, _, _)) => {
-                    let mut state_lock = self.state.write().unwrap();
-                    if *state_lock == ProcessState::Stopping {
-                        *state_lock = ProcessState::Stopped;
+                drop(state_lock);
+            }
+            wait::WaitStatus::Signaled(_, sig, _) => {
+                let mut state_lock = self.state.write().unwrap();
+                match *state_lock {
+                    ProcessState::Stopping(_) => *state_lock = ProcessState::Stopped,
+                    ProcessState::Starting(_) => {
+                        warn!(
+                            "process {} killed by signal {:?} while starting",
+                            self.config.name, sig
+                        );
+                        *state_lock = ProcessState::Backoff;
+                    }
+                    _ if self.is_oneshot() => {
+                        warn!(
+                            "oneshot process {} killed by signal {:?}",
+                            self.config.name, sig
+                        );
+                        *state_lock = ProcessState::Backoff;
+                    }
+                    _ => {
+                        info!("process {} killed by signal {:?}", self.config.name, sig);
+                        *state_lock = ProcessState::Killed(sig);
+                    }
+                }
+                drop(state_lock);
+            }
+            s => {
+                warn!(
+                    "process {} received unexpected status {:?}",
+                    self.config.name, s
+                );
+                let mut state_lock = self.state.write().unwrap();
+                *state_lock = ProcessState::Stopped;
+                drop(state_lock);
+            }
+        }
+        let duration = match self.spawned_at {
+            Some(spawned_at) => spawned_at.elapsed(),
+            None => Duration::from_secs(0),
+        };
+        // a failed start is recorded with what ended it
+        let state = match (self.state.read().unwrap().clone(), status) {
+            (ProcessState::Backoff, wait::WaitStatus::Exited(_, code)) => {
+                ProcessState::Exited(code)
+            }
+            (ProcessState::Backoff, wait::WaitStatus::Signaled(_, sig, _)) => {
+                ProcessState::Killed(sig)
+            }
+            (state, _) => state,
+        };
+        self.record_run(RunResult {
+            state: state,
+            duration: duration,
+        });
+        Some(status)
+    }
+
+    /// Get pid of the process if it is alive
+    pub fn pid(&self) -> Option<Pid> {
+        match *self.state.read().unwrap() {
+            ProcessState::Starting(pid)
+            | ProcessState::Running(pid)
+            | ProcessState::Stopping(pid) => Some(pid),
+            _ => None,
+        }
+    }
+
+    /// Report exit status of the process, called by the reaper
+    pub fn notify_exit(&self, status: wait::WaitStatus) {
+        *self.exit_status.lock().unwrap() = Some(status);
+        self.exited.notify_all();
+    }
+
+    /// Report exit status of a helper command, false if pid is not one of ours
+    pub fn notify_helper(&self, pid: Pid, status: wait::WaitStatus) -> bool {
+        let mut helpers = self.helpers.lock().unwrap();
+        match helpers.iter().position(|&(p, _)| p == pid) {
+            Some(idx) => {
+                let (_, sender) = helpers.remove(idx);
+                let _ = sender.send(status);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Track children of the daemon coming from the process, they were orphaned and adopted
+    pub fn adopt_orphans(&self, children: &[(Pid, Pid)]) {
+        let group = match self.group {
+            Some(group) => group,
+            None => return,
+        };
+        let in_cgroup = self
+            .cgroup
+            .as_ref()
+            .map(|cgroup| cgroup.procs())
+            .unwrap_or(Vec::new());
+        let main = self.pid();
+        let helpers = self.helpers.lock().unwrap();
+        let mut orphans = self.orphans.lock().unwrap();
+        // forget orphans reaped before they were adopted
+        orphans.retain(|pid| children.iter().any(|&(child, _)| child == *pid));
+        for &(pid, pgid) in children {
+            if Some(pid) == main
+                || helpers.iter().any(|&(helper, _)| helper == pid)
+                || orphans.contains(&pid)
+            {
+                continue;
+            }
+            // orphans leading their own group pass it on to their children
+            if pgid == group || orphans.contains(&pgid) || in_cgroup.contains(&pid) {
+                info!("adopted orphan pid {} of process {}", pid, self.config.name);
+                orphans.push(pid);
+            }
+        }
+    }
+
+    /// Report exit of an adopted orphan, false if pid is not one of ours
+    pub fn notify_orphan_exit(&self, pid: Pid) -> bool {
+        let mut orphans = self.orphans.lock().unwrap();
+        match orphans.iter().position(|&p| p == pid) {
+            Some(idx) => {
+                orphans.remove(idx);
+                debug!("reaped orphan pid {} of process {}", pid, self.config.name);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Get orphans of the process adopted by the daemon
+    pub fn orphans(&self) -> Vec<Pid> {
+        self.orphans.lock().unwrap().clone()
+    }
+
+    /// Send signal to every adopted orphan
+    fn signal_orphans(&self, signal: &StopSignal) {
+        for pid in self.orphans() {
+            if let Err(e) = signal.kill(pid, false) {
+                error!("killing orphan pid {} failed", pid);
+                trace!("error: {}", e);
+            }
+        }
+    }
+
+    /// Get pids of the overlapping runs of a scheduled process
+    pub fn overlaps(&self) -> Vec<Pid> {
+        self.overlaps.lock().unwrap().clone()
+    }
+
+    /// Send signal to the group of every overlapping run
+    fn signal_overlaps(&self, signal: &StopSignal) {
+        for pid in self.overlaps() {
+            if let Err(e) = signal.kill(pid, true) {
+                error!("killing overlapping run pid {} failed", pid);
+                trace!("error: {}", e);
+            }
+        }
+    }
+
+    /// Spawn an overlapping run of the command in the cgroup of the process
+    ///
+    /// The run is tracked until end_overlap, so that stopping the process stops it too.
+    pub fn spawn_overlap(&self) -> Result<(Pid, Receiver<wait::WaitStatus>), String> {
+        let command = self.config.command.clone();
+        let (pid, receiver) = self.fork_helper(&command, &[], true, true)?;
+        self.overlaps.lock().unwrap().push(pid);
+        Ok((pid, receiver))
+    }
+
+    /// Forget an overlapping run once its status was received
+    pub fn end_overlap(&self, pid: Pid) {
+        self.overlaps.lock().unwrap().retain(|&p| p != pid);
+    }
+
+    /// Spawn a helper command as the process would run, its status is sent on the receiver
+    ///
+    /// Output of the helper goes to the log files of the process if log_output is set,
+    /// it is discarded otherwise.
+    pub fn spawn_helper(
+        &self,
+        command: &str,
+        env: &[(&str, String)],
+        log_output: bool,
+    ) -> Result<(Pid, Receiver<wait::WaitStatus>), String> {
+        self.fork_helper(command, env, log_output, false)
+    }
+
+    fn fork_helper(
+        &self,
+        command: &str,
+        env: &[(&str, String)],
+        log_output: bool,
+        in_cgroup: bool,
+    ) -> Result<(Pid, Receiver<wait::WaitStatus>), String> {
+        let user = self.resolve_user()?;
+        let mut command = self.build_command(command, user.as_ref())?;
+        for &(name, ref value) in env {
+            command.set_env(name, value);
+        }
+        let (stdout_log, stderr_log) = if log_output {
+            let stdout_log = open_log(&self.config.stdout_logfile);
+            if self.config.redirect_stderr {
+                (stdout_log, None)
+            } else {
+                (stdout_log, open_log(&self.config.stderr_logfile))
+            }
+        } else {
+            (None, None)
+        };
+        let (sender, receiver) = mpsc::channel();
+        // the reaper looks helpers up under this lock, so register before it sees the exit
+        let mut helpers = self.helpers.lock().unwrap();
+        let pid = match fork() {
+            Ok(ForkResult::Child) => {
+                let _ = setpgid(Pid::from_raw(0), Pid::from_raw(0));
+                if let (true, Some(cgroup)) = (in_cgroup, self.cgroup.as_ref()) {
+                    if cgroup.enter().is_err() {
+                        ::std::process::exit(1);
                     }
-                    drop(state_lock);
                 }
-                Ok(s) => {
-                    blather!("pid {} received status {:#?}", pid, s);
+                self.prepare_child(user.as_ref());
+                if let Ok(null) = fcntl::open("/dev/null", fcntl::O_RDWR, stat::Mode::empty()) {
+                    let _ = dup2(null, 0);
+                    let _ = dup2(null, 1);
+                    let _ = dup2(null, 2);
                 }
-                Err(e) => {
-                    warn!("unexpected error for pid {}", pid);
-                    trace!("error: {}", e);
-                    let mut state_lock = self.state.write().unwrap();
-                    *state_lock = ProcessState::Stopped;
-                    drop(state_lock);
-                    break;
+                if let Some(ref log) = stdout_log {
+                    let _ = dup2(log.as_raw_fd(), 1);
+                    if self.config.redirect_stderr {
+                        let _ = dup2(log.as_raw_fd(), 2);
+                    }
                 }
+                if let Some(ref log) = stderr_log {
+                    let _ = dup2(log.as_raw_fd(), 2);
+                }
+                let _ = command.exec();
+                ::std::process::exit(127);
             }
+            Ok(ForkResult::Parent { child }) => child,
+            Err(e) => return Err(format!("cannot fork: {}", e)),
+        };
+        helpers.push((pid, sender));
+        Ok((pid, receiver))
+    }
+
+    /// Get result of the last finished run
+    pub fn last_run(&self) -> Option<RunResult> {
+        self.last_run.lock().unwrap().clone()
+    }
+
+    /// Record result of a finished run
+    pub fn record_run(&self, run: RunResult) {
+        if self.config.schedule.is_some() {
+            info!(
+                "run of process {} ended {:?} after {}",
+                self.config.name,
+                run.state,
+                format_duration(run.duration)
+            );
+        }
+        *self.last_run.lock().unwrap() = Some(run);
+    }
+
+    /// Get next scheduled run, in secs since epoch
+    pub fn next_run(&self) -> Option<i64> {
+        *self.next_run.lock().unwrap()
+    }
+
+    /// Compute next scheduled run after timestamp
+    pub fn schedule_next(&self, timestamp: i64) {
+        let next = match self.config.schedule {
+            Some(ref schedule) => schedule.next_after(timestamp),
+            None => return,
+        };
+        if next.is_none() {
+            warn!("schedule of process {} never fires", self.config.name);
+        }
+        *self.next_run.lock().unwrap() = next;
+    }
+
+    /// Build command with the environment of the process
+    fn build_command(&self, command: &str, user: Option<&User>) -> Result<Command, String> {
+        let vars = self.config.env_vars()?;
+        let mut command = Command::new(command, &vars, self.config.clean_env);
+        if let Some(user) = user {
+            command.set_env("HOME", &user.home.display().to_string());
+            command.set_env("USER", &user.name);
+            command.set_env("LOGNAME", &user.name);
+        }
+        Ok(command)
+    }
+
+    /// Forget failures of previous runs
+    fn reset_failures(&mut self) {
+        self.count_fail = 0;
+        if *self.state.read().unwrap() == ProcessState::Fatal {
+            *self.state.write().unwrap() = ProcessState::Stopped;
+        }
+    }
+
+    /// Get command of a hook by name
+    fn hook_command(&self, hook: &str) -> Option<&String> {
+        match hook {
+            "pre_start" => self.config.pre_start.as_ref(),
+            "post_start" => self.config.post_start.as_ref(),
+            "pre_stop" => self.config.pre_stop.as_ref(),
+            "post_stop" => self.config.post_stop.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Handle process exit, return delay before next start if it must be restarted
+    fn next_start(&mut self) -> Option<Duration> {
+        let state = self.state.read().unwrap().clone();
+        match state {
+            ProcessState::Backoff => {
+                self.handle_fail();
+                if *self.state.read().unwrap() == ProcessState::Fatal {
+                    return None;
+                }
+                let delay = self.backoff_delay();
+                info!(
+                    "process {} backoff, retrying in {}s",
+                    self.config.name,
+                    delay.as_secs()
+                );
+                Some(delay)
+            }
+            ProcessState::Stopped if self.restart.swap(false, Ordering::SeqCst) => {
+                info!("restarting process {}", self.config.name);
+                Some(Duration::from_secs(0))
+            }
+            ProcessState::Exited(_) | ProcessState::Killed(_) => {
+                self.count_fail = 0;
+                // a scheduled run ends at exit, the next one is started by the scheduler
+                if self.config.schedule.is_some() || !self.should_restart() {
+                    return None;
+                }
+                info!("restarting process {}", self.config.name);
+                Some(Duration::from_secs(0))
+            }
+            _ => None,
+        }
+    }
+
+    /// Check if process needs to be restarted according to auto_restart
+    pub fn should_restart(&self) -> bool {
+        let state = self.state.read().unwrap();
+        match self.config.auto_restart {
+            AutoRestartCondition::True => match *state {
+                ProcessState::Exited(_) | ProcessState::Killed(_) => true,
+                _ => false,
+            },
+            AutoRestartCondition::False => false,
+            AutoRestartCondition::Unexpected => match *state {
+                ProcessState::Exited(code) => !self.is_expected_exit(code),
+                ProcessState::Killed(_) => true,
+                _ => false,
+            },
         }
     }
 
@@ -263,43 +1072,197 @@ impl Process {
         self.state.read().unwrap()
     }
 
+    /// Resolve the user to run as, None if process runs as the daemon user
+    fn resolve_user(&self) -> Result<Option<User>, String> {
+        let name = match self.config.user {
+            Some(ref name) => name,
+            None => return Ok(None),
+        };
+        let user = match User::lookup(name) {
+            Some(user) => user,
+            None => return Err(format!("unknown user {}", name)),
+        };
+        if user.uid == geteuid() {
+            return Ok(Some(user));
+        }
+        if !geteuid().is_root() {
+            return Err(format!(
+                "cannot run as user {}, daemon is not running as root",
+                name
+            ));
+        }
+        Ok(Some(user))
+    }
+
+    /// Switch to user identity, must be called in child before exec
+    fn switch_user(user: &User) -> ::nix::Result<()> {
+        if user.uid == geteuid() {
+            return Ok(());
+        }
+        ffi::initgroups(&user.name, user.gid)?;
+        setgid(user.gid)?;
+        setuid(user.uid)
+    }
+
+    /// Apply umask, rlimits, user and directory in child before exec, exit on failure
+    fn prepare_child(&self, user: Option<&User>) {
+        if let Some(mask) = self.config.umask {
+            stat::umask(stat::Mode::from_bits_truncate(mask));
+        }
+        for (resource, limit) in self.config.rlimits.limits() {
+            match ffi::setrlimit(resource, limit, limit) {
+                Ok(_) => {}
+                Err(e) => {
+                    error!(
+                        "cannot set limit {:?} to {} for process {}",
+                        resource, limit, self.config.name
+                    );
+                    trace!("error: {}", e);
+                    ::std::process::exit(1);
+                }
+            }
+        }
+        if let Some(user) = user {
+            match Process::switch_user(user) {
+                Ok(_) => {}
+                Err(e) => {
+                    error!(
+                        "cannot switch to user {} for process {}",
+                        user.name, self.config.name
+                    );
+                    trace!("error: {}", e);
+                    ::std::process::exit(1);
+                }
+            }
+        }
+        if let Some(ref wd) = self.config.directory {
+            match chdir(wd) {
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("cannot change directory for process {}", self.config.name);
+                    trace!("error: {}", e);
+                    ::std::process::exit(1);
+                }
+            }
+        }
+    }
+
     pub fn spawn(&mut self) {
-        let mut state_lock = self.state.write().unwrap();
-        if *state_lock == ProcessState::Fatal {
+        if *self.state.read().unwrap() == ProcessState::Fatal {
             return;
         }
         trace!("spawning process {}", self.config.name);
-        *state_lock = ProcessState::Starting;
-        drop(state_lock);
-        let (c_stdin, p_stdin) = pipe().unwrap();
-        let (p_stdout, c_stdout) = pipe().unwrap();
-        let (p_stderr, c_stderr) = pipe().unwrap();
+        let user = match self.resolve_user() {
+            Ok(user) => user,
+            Err(e) => {
+                error!("cannot spawn process {}: {}", self.config.name, e);
+                let mut state_lock = self.state.write().unwrap();
+                *state_lock = ProcessState::Fatal;
+                return;
+            }
+        };
+        let mut command = match self.build_command(&self.config.command, user.as_ref()) {
+            Ok(command) => command,
+            Err(e) => {
+                error!("cannot spawn process {}: {}", self.config.name, e);
+                let mut state_lock = self.state.write().unwrap();
+                *state_lock = ProcessState::Fatal;
+                return;
+            }
+        };
+        if !self.sockets.is_empty() {
+            let names = self
+                .sockets
+                .iter()
+                .map(|socket| socket.name())
+                .collect::<Vec<&str>>();
+            command.set_env("LISTEN_FDS", &self.sockets.len().to_string());
+            command.set_env("LISTEN_FDNAMES", &names.join(":"));
+        }
+        if let Some(ref socket) = self.notify_socket {
+            command.set_env("NOTIFY_SOCKET", &socket.path().display().to_string());
+        }
+        if let Some(secs) = self.config.watchdog_secs {
+            command.set_env("WATCHDOG_USEC", &(secs * 1000000).to_string());
+        }
+        *self.notify.lock().unwrap() = NotifyState::new();
+        if let Some(ref cgroup) = self.cgroup {
+            match cgroup.setup(&self.config) {
+                Ok(_) => self.oom_kills = cgroup.oom_kills(),
+                Err(e) => {
+                    error!(
+                        "cannot set up cgroup of process {}: {}",
+                        self.config.name, e
+                    );
+                    let mut state_lock = self.state.write().unwrap();
+                    *state_lock = ProcessState::Fatal;
+                    return;
+                }
+            }
+        }
+        let ((c_stdin, p_stdin), (p_stdout, c_stdout)) = if self.config.tty {
+            match open_pty() {
+                Ok(fds) => fds,
+                Err(e) => {
+                    error!("cannot open pty for process {}: {}", self.config.name, e);
+                    let mut state_lock = self.state.write().unwrap();
+                    *state_lock = ProcessState::Fatal;
+                    return;
+                }
+            }
+        } else {
+            (pipe().unwrap(), pipe().unwrap())
+        };
+        let stderr_pipe = if self.config.redirect_stderr || self.config.tty {
+            None
+        } else {
+            Some(pipe().unwrap())
+        };
         match fork() {
             Ok(ForkResult::Child) => {
-                if let Some(mask) = self.config.umask {
-                    stat::umask(stat::Mode::from_bits_truncate(mask));
-                }
-                if let Some(ref mut wd) = self.config.directory {
-                    match chdir(wd) {
-                        Ok(_) => {}
-                        Err(e) => {
-                            trace!("error in process {}: {}", self.config.name, e);
-                            self.handle_fail();
-                        }
+                if self.config.tty {
+                    let _ = setsid();
+                    let _ = ffi::set_controlling_tty(c_stdin);
+                } else {
+                    let _ = setpgid(Pid::from_raw(0), Pid::from_raw(0));
+                }
+                if let Some(ref cgroup) = self.cgroup {
+                    if let Err(e) = cgroup.enter() {
+                        error!("cannot enter cgroup of process {}", self.config.name);
+                        trace!("error: {}", e);
+                        ::std::process::exit(1);
                     }
                 }
-
+                self.prepare_child(user.as_ref());
                 close(p_stdin).unwrap();
                 close(p_stdout).unwrap();
-                close(p_stderr).unwrap();
                 dup2(c_stdin, 0).unwrap();
                 dup2(c_stdout, 1).unwrap();
-                dup2(c_stderr, 2).unwrap();
+                match stderr_pipe {
+                    Some((p_stderr, c_stderr)) => {
+                        close(p_stderr).unwrap();
+                        dup2(c_stderr, 2).unwrap();
+                        close(c_stderr).unwrap();
+                    }
+                    None => {
+                        dup2(c_stdout, 2).unwrap();
+                    }
+                }
                 close(c_stdin).unwrap();
                 close(c_stdout).unwrap();
-                close(c_stderr).unwrap();
+                if !self.sockets.is_empty() {
+                    if let Err(e) = socket::pass(&self.sockets) {
+                        error!("cannot pass sockets to process {}", self.config.name);
+                        trace!("error: {}", e);
+                        ::std::process::exit(1);
+                    }
+                    command.set_env("LISTEN_PID", &getpid().to_string());
+                }
+                if self.config.watchdog_secs.is_some() {
+                    command.set_env("WATCHDOG_PID", &getpid().to_string());
+                }
                 trace!("executing command for process {}", self.config.name);
-                match self.command.exec() {
+                match command.exec() {
                     Ok(_) => {
                         trace!("command executed {}", self.config.name);
                     }
@@ -311,25 +1274,562 @@ impl Process {
                 ::std::process::exit(1);
             }
             Ok(ForkResult::Parent { child }) => {
+                // the child leads its own session with a tty, it cannot be moved
+                if !self.config.tty {
+                    let _ = setpgid(child, child);
+                }
+                self.group = Some(child);
                 let mut holder_lock = self.holder.lock().unwrap();
-                *holder_lock = ProcessHolder::new()
+                let mut holder = ProcessHolder::new()
                     .stdin(p_stdin)
                     .stdout(p_stdout)
-                    .stderr(p_stderr);
+                    .stdout_log(
+                        &self.config.stdout_logfile,
+                        self.config.stdout_logfile_maxbytes,
+                    );
+                if let Some((p_stderr, c_stderr)) = stderr_pipe {
+                    holder = holder.stderr(p_stderr).stderr_log(
+                        &self.config.stderr_logfile,
+                        self.config.stderr_logfile_maxbytes,
+                    );
+                    close(c_stderr).unwrap();
+                }
+                if self.config.tty {
+                    holder = holder.pty();
+                }
+                // attached fg sessions follow the process across restarts
+                holder.subscribers = ::std::mem::replace(&mut holder_lock.subscribers, Vec::new());
+                *holder_lock = holder;
                 drop(holder_lock);
+                io_loop::wake();
+                *self.health.lock().unwrap() = Health::Unknown;
+                self.spawned_at = Some(Instant::now());
+                self.restart.store(false, Ordering::SeqCst);
                 close(c_stdin).unwrap();
                 close(c_stdout).unwrap();
-                close(c_stderr).unwrap();
                 let mut state_lock = self.state.write().unwrap();
-                *state_lock = ProcessState::Running(child);
+                if self.start_secs() == 0
+                    && self.config.readiness.is_none()
+                    && self.config.kind != ProgramKind::Notify
+                {
+                    *state_lock = ProcessState::Running(child);
+                } else {
+                    *state_lock = ProcessState::Starting(child);
+                }
                 drop(state_lock);
                 info!("process {} spawned on pid {}", self.config.name, child);
             }
             Err(e) => {
-                // TODO: respawn
                 critical!("error: {:#?}", e);
-                self.handle_fail();
+                let mut state_lock = self.state.write().unwrap();
+                *state_lock = ProcessState::Backoff;
+                drop(state_lock);
             }
         }
     }
 }
+
+/// Keep a spawned process alive until it stops, fails or becomes fatal
+///
+/// The caller must have claimed the process with claim_supervisor, it is released on return.
+pub fn supervise(process: ProcessSync) {
+    loop {
+        let state = process.read().unwrap().get_state().clone();
+        if let ProcessState::Starting(pid) = state {
+            let process = process.clone();
+            thread::spawn(move || {
+                let (start_secs, readiness, notify_timeout) = {
+                    let process = process.read().unwrap();
+                    let notify_timeout = match process.config.kind {
+                        ProgramKind::Notify => Some(process.config.notify_timeout_secs),
+                        _ => None,
+                    };
+                    (
+                        process.start_secs(),
+                        process.config.readiness.clone(),
+                        notify_timeout,
+                    )
+                };
+                let started = Instant::now();
+                thread::sleep(Duration::from_secs(start_secs));
+                if let Some(timeout) = notify_timeout {
+                    let deadline = started + Duration::from_secs(timeout);
+                    if !wait_notified(&process, pid, deadline) {
+                        process.read().unwrap().fail_start(pid);
+                        return;
+                    }
+                }
+                if let Some(readiness) = readiness {
+                    let deadline = started + Duration::from_secs(readiness.timeout);
+                    if !wait_ready(&process, pid, &readiness.check, deadline) {
+                        process.read().unwrap().fail_start(pid);
+                        return;
+                    }
+                }
+                process.read().unwrap().confirm_start(pid);
+                run_post_start(&process, pid);
+            });
+        }
+        if let ProcessState::Running(pid) = state {
+            if process.read().unwrap().config.post_start.is_some() {
+                let process = process.clone();
+                thread::spawn(move || run_post_start(&process, pid));
+            }
+        }
+        match state {
+            ProcessState::Starting(pid) | ProcessState::Running(pid) => {
+                let healthcheck = process.read().unwrap().config.healthcheck.clone();
+                if let Some(healthcheck) = healthcheck {
+                    let process = process.clone();
+                    thread::spawn(move || monitor_health(process, pid, healthcheck));
+                }
+            }
+            _ => {}
+        }
+        let status = process.read().unwrap().track_state();
+        if let Some(status) = status {
+            run_post_stop(&process, status);
+        }
+        let delay = match process.write().unwrap().next_start() {
+            Some(delay) => delay,
+            None => break,
+        };
+        thread::sleep(delay);
+        if is_shutting_down() {
+            break;
+        }
+        start(&process);
+    }
+    process
+        .read()
+        .unwrap()
+        .supervised
+        .store(false, Ordering::SeqCst);
+}
+
+/// Run pre_start hook then spawn process, a failing hook counts as a failed start
+fn start(process: &ProcessSync) {
+    if *process.read().unwrap().get_state() == ProcessState::Fatal || is_shutting_down() {
+        return;
+    }
+    if !run_hook(process, "pre_start", &[]) {
+        let process = process.read().unwrap();
+        warn!("process {} not spawned", process.config.name);
+        *process.state.write().unwrap() = ProcessState::Backoff;
+        return;
+    }
+    let mut process = process.write().unwrap();
+    // checked under the lock, a shutdown stopping the process then sees its pid
+    if is_shutting_down() {
+        return;
+    }
+    process.spawn();
+}
+
+/// Stop process with stop_signal, send SIGKILL if it is still alive after stop_wait_secs
+///
+/// The process lock is not held while waiting, the reaper needs it to report exits.
+pub fn stop(process: &ProcessSync) {
+    let (pid, stop_wait) = {
+        let process = process.read().unwrap();
+        process.adopt_orphans(&reaper::daemon_children());
+        (
+            process.pid(),
+            Duration::from_secs(process.config.stop_wait_secs),
+        )
+    };
+    if pid.is_none() {
+        let has_leftovers = {
+            let process = process.read().unwrap();
+            let has_leftovers = !process.orphans().is_empty() || !process.overlaps().is_empty();
+            if has_leftovers {
+                info!("stopping leftovers of process {}", process.config.name);
+                process.signal_orphans(&process.config.stop_signal);
+                process.signal_overlaps(&process.config.stop_signal);
+            }
+            has_leftovers
+        };
+        if has_leftovers {
+            wait_leftovers(process, Instant::now() + stop_wait);
+        }
+        return;
+    }
+    // an exit during pre_stop is then a stop, not a crash to restart from
+    let pid = {
+        let process = process.read().unwrap();
+        let state = process.get_state().clone();
+        match state {
+            ProcessState::Starting(pid) | ProcessState::Running(pid) => {
+                *process.state.write().unwrap() = ProcessState::Stopping(pid);
+                pid
+            }
+            _ => return,
+        }
+    };
+    run_hook(process, "pre_stop", &[]);
+    {
+        let process = process.read().unwrap();
+        // pid is reaped already if pre_stop made it exit
+        if *process.get_state() == ProcessState::Stopping(pid) {
+            match process
+                .config
+                .stop_signal
+                .kill(pid, process.config.stop_as_group)
+            {
+                Ok(_) => {}
+                Err(e) => {
+                    error!("killing pid {} failed", pid);
+                    trace!("error: {}", e);
+                }
+            }
+        }
+        process.signal_orphans(&process.config.stop_signal);
+        process.signal_overlaps(&process.config.stop_signal);
+    }
+    let deadline = Instant::now() + stop_wait;
+    loop {
+        {
+            let process = process.read().unwrap();
+            if *process.get_state() != ProcessState::Stopping(pid) {
+                break;
+            }
+            if Instant::now() >= deadline {
+                process.escalate_stop(pid);
+                break;
+            }
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    wait_leftovers(process, deadline);
+}
+
+/// Stop process through stop and start it again once stopped
+pub fn restart(process: &ProcessSync) {
+    {
+        let process = process.read().unwrap();
+        if process.pid().is_none() {
+            return;
+        }
+        process.restart.store(true, Ordering::SeqCst);
+    }
+    stop(process);
+}
+
+/// Wait orphans and overlapping runs of process to be reaped, kill the ones still alive at
+/// deadline
+fn wait_leftovers(process: &ProcessSync, deadline: Instant) {
+    loop {
+        {
+            let process = process.read().unwrap();
+            if process.orphans().is_empty() && process.overlaps().is_empty() {
+                return;
+            }
+            if Instant::now() >= deadline {
+                warn!(
+                    "leftovers of process {} still alive after {}s, sending SIGKILL",
+                    process.config.name, process.config.stop_wait_secs
+                );
+                process.signal_orphans(&StopSignal::Kill);
+                process.signal_overlaps(&StopSignal::Kill);
+                if let Some(ref cgroup) = process.cgroup {
+                    cgroup.kill_all();
+                }
+                return;
+            }
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Run hook with the user, directory and env of the process, false if it failed
+///
+/// The process lock is only held to spawn the hook, the reaper needs it to report the exit.
+fn run_hook(process: &ProcessSync, hook: &str, env: &[(&str, String)]) -> bool {
+    let (name, timeout, spawned) = {
+        let process = process.read().unwrap();
+        let command = match process.hook_command(hook) {
+            Some(command) => command.clone(),
+            None => return true,
+        };
+        debug!("running {} hook of process {}", hook, process.config.name);
+        let mut env = env.to_vec();
+        env.push(("TASKMASTER_PROCESS_NAME", process.config.name.clone()));
+        (
+            process.config.name.clone(),
+            Duration::from_secs(process.config.hook_timeout_secs),
+            process.spawn_helper(&command, &env, true),
+        )
+    };
+    let status = spawned.and_then(|(pid, receiver)| wait_helper(pid, &receiver, Some(timeout)));
+    match status {
+        Ok(wait::WaitStatus::Exited(_, 0)) => true,
+        Ok(status) => {
+            warn!("{} hook of process {} failed with {:?}", hook, name, status);
+            false
+        }
+        Err(e) => {
+            warn!("{} hook of process {} failed: {}", hook, name, e);
+            false
+        }
+    }
+}
+
+/// Run post_stop hook with the exit code of the process, 128 + signal if it was killed
+fn run_post_stop(process: &ProcessSync, status: wait::WaitStatus) {
+    let exit_code = match status {
+        wait::WaitStatus::Exited(_, code) => code as u8 as i32,
+        wait::WaitStatus::Signaled(_, sig, _) => 128 + sig as i32,
+        _ => -1,
+    };
+    run_hook(
+        process,
+        "post_stop",
+        &[("TASKMASTER_EXIT_CODE", exit_code.to_string())],
+    );
+}
+
+/// Start a scheduled run, failures of previous runs are forgotten
+pub fn start_scheduled(process: &ProcessSync) {
+    process.write().unwrap().reset_failures();
+    info!(
+        "starting scheduled run of process {}",
+        process.read().unwrap().config.name
+    );
+    start(process);
+}
+
+/// Wait for pid to send READY=1 until deadline, false if it did not or stopped starting
+fn wait_notified(process: &ProcessSync, pid: Pid, deadline: Instant) -> bool {
+    loop {
+        {
+            let process = process.read().unwrap();
+            if *process.get_state() != ProcessState::Starting(pid) {
+                return false;
+            }
+            if process.notify_state().ready {
+                return true;
+            }
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Start a process on a connection to its sockets, failures of previous runs are forgotten
+pub fn start_on_connection(process: &ProcessSync) {
+    process.write().unwrap().reset_failures();
+    info!(
+        "starting process {} on connection",
+        process.read().unwrap().config.name
+    );
+    start(process);
+}
+
+/// Run post_start hook if pid is running
+fn run_post_start(process: &ProcessSync, pid: Pid) {
+    if *process.read().unwrap().get_state() == ProcessState::Running(pid) {
+        run_hook(process, "post_start", &[]);
+    }
+}
+
+/// Run a helper command as the process would run, return its exit code
+///
+/// The process lock is only held to spawn the helper, the reaper needs it to report the exit.
+pub fn run_helper(process: &ProcessSync, command: &str, timeout: Duration) -> Result<i8, String> {
+    let (pid, receiver) = process.read().unwrap().spawn_helper(command, &[], false)?;
+    match wait_helper(pid, &receiver, Some(timeout))? {
+        wait::WaitStatus::Exited(_, code) => Ok(code),
+        wait::WaitStatus::Signaled(_, sig, _) => Err(format!("killed by signal {:?}", sig)),
+        s => Err(format!("unexpected status {:?}", s)),
+    }
+}
+
+/// Run readiness check until it passes, false if deadline is reached or pid is gone
+fn wait_ready(process: &ProcessSync, pid: Pid, ready_check: &Check, deadline: Instant) -> bool {
+    loop {
+        if *process.read().unwrap().get_state() != ProcessState::Starting(pid) {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return false;
+        }
+        match check::run(process, ready_check, deadline - now) {
+            Ok(_) => return true,
+            Err(e) => blather!(
+                "process {} not ready: {}",
+                process.read().unwrap().proc_name(),
+                e
+            ),
+        }
+        thread::sleep(Duration::from_millis(500));
+    }
+}
+
+/// Run health checks while pid is alive, restart it when it reaches the failure threshold
+fn monitor_health(process: ProcessSync, pid: Pid, healthcheck: HealthCheckConfig) {
+    loop {
+        thread::sleep(Duration::from_secs(healthcheck.interval));
+        let state = process.read().unwrap().get_state().clone();
+        match state {
+            ProcessState::Running(p) if p == pid => {}
+            ProcessState::Starting(p) if p == pid => continue,
+            _ => return,
+        }
+        // checks can take up to timeout, they run without the process lock
+        let timeout = Duration::from_secs(healthcheck.timeout);
+        let result = check::run(&process, &healthcheck.check, timeout);
+        let count = {
+            let process = process.read().unwrap();
+            if *process.get_state() != ProcessState::Running(pid) {
+                return;
+            }
+            match result {
+                Ok(_) => {
+                    *process.health.lock().unwrap() = Health::Healthy;
+                    continue;
+                }
+                Err(ref e) => {
+                    debug!("process {} health check failed: {}", process.proc_name(), e);
+                    process.health_failed()
+                }
+            }
+        };
+        if count >= healthcheck.failures {
+            warn!(
+                "process {} failed {} health checks in a row, restarting: {}",
+                process.read().unwrap().proc_name(),
+                count,
+                result.unwrap_err()
+            );
+            restart(&process);
+            return;
+        }
+    }
+}
+
+/// Check if every program process depends on is ready
+fn dependencies_ready(process: &Process, processes: &[ProcessSync]) -> bool {
+    process.depends_on().iter().all(|dep| {
+        processes
+            .iter()
+            .filter(|p| p.read().unwrap().program_name() == dep)
+            .all(|p| p.read().unwrap().is_ready())
+    })
+}
+
+/// Check if a dependency of process is still on its way to be ready
+fn dependencies_pending(process: &Process, processes: &[ProcessSync]) -> bool {
+    process.depends_on().iter().any(|dep| {
+        processes
+            .iter()
+            .filter(|p| p.read().unwrap().program_name() == dep)
+            .any(|p| p.read().unwrap().is_pending())
+    })
+}
+
+/// Split out processes having the given priority
+fn split_priority(
+    processes: Vec<ProcessSync>,
+    priority: u16,
+) -> (Vec<ProcessSync>, Vec<ProcessSync>) {
+    processes
+        .into_iter()
+        .partition(|p| p.read().unwrap().priority() == priority)
+}
+
+/// Start processes by ascending priority, each tier waits for the previous one to settle
+///
+/// A process only starts once the programs it depends on are ready, processes
+/// whose dependencies never get ready are not started.
+pub fn start_processes(processes: &[ProcessSync]) {
+    for process in processes.iter() {
+        let process = process.read().unwrap();
+        if !process.depends_on().is_empty() && !process.is_triggered() {
+            process.set_waiting();
+        }
+    }
+    let mut remaining: Vec<ProcessSync> = processes
+        .iter()
+        .filter(|p| !p.read().unwrap().is_triggered())
+        .cloned()
+        .collect();
+    while !remaining.is_empty() && !is_shutting_down() {
+        let (ready, blocked): (Vec<ProcessSync>, Vec<ProcessSync>) = remaining
+            .into_iter()
+            .partition(|p| dependencies_ready(&p.read().unwrap(), processes));
+        if ready.is_empty() {
+            if blocked
+                .iter()
+                .any(|p| dependencies_pending(&p.read().unwrap(), processes))
+            {
+                thread::sleep(Duration::from_millis(100));
+                remaining = blocked;
+                continue;
+            }
+            for process in blocked.iter() {
+                process
+                    .read()
+                    .unwrap()
+                    .abort_start("dependencies are not running");
+            }
+            break;
+        }
+        let priority = ready
+            .iter()
+            .map(|p| p.read().unwrap().priority())
+            .min()
+            .unwrap();
+        let (tier, rest) = split_priority(ready, priority);
+        debug!("starting priority tier {}", priority);
+        for process in tier.iter() {
+            if !process.read().unwrap().claim_supervisor() {
+                continue;
+            }
+            start(process);
+            let process = process.clone();
+            thread::spawn(move || supervise(process));
+        }
+        while !tier.iter().all(|p| p.read().unwrap().is_settled()) {
+            thread::sleep(Duration::from_millis(100));
+        }
+        remaining = rest.into_iter().chain(blocked.into_iter()).collect();
+    }
+}
+
+/// Stop processes by descending priority, each tier is stopped before the next one
+///
+/// A process is only stopped once every process depending on it is stopped.
+pub fn stop_processes(processes: &[ProcessSync]) {
+    let mut remaining = processes.to_vec();
+    while !remaining.is_empty() {
+        let (mut free, mut needed): (Vec<ProcessSync>, Vec<ProcessSync>) =
+            remaining.iter().cloned().partition(|p| {
+                let name = p.read().unwrap().program_name().to_owned();
+                !remaining
+                    .iter()
+                    .any(|other| other.read().unwrap().depends_on().contains(&name))
+            });
+        if free.is_empty() {
+            free = needed;
+            needed = Vec::new();
+        }
+        let priority = free
+            .iter()
+            .map(|p| p.read().unwrap().priority())
+            .max()
+            .unwrap();
+        let (tier, rest) = split_priority(free, priority);
+        debug!("stopping priority tier {}", priority);
+        let handles = tier
+            .into_iter()
+            .map(|process| thread::spawn(move || stop(&process)))
+            .collect::<Vec<_>>();
+        for handle in handles {
+            let _ = handle.join();
+        }
+        remaining = rest.into_iter().chain(needed.into_iter()).collect();
+    }
+}
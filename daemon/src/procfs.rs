@@ -0,0 +1,87 @@
+//! Read state of processes from /proc
+
+use nix::unistd::Pid;
+use std::fs::{self, File};
+use std::io::{self, Read};
+
+/// Fields of `/proc/<pid>/stat` used by the daemon
+#[derive(Clone, Debug)]
+pub struct Stat {
+    /// State letter, `Z` for a zombie
+    pub state: char,
+    /// Parent pid
+    pub ppid: Pid,
+    /// Process group
+    pub pgrp: Pid,
+    /// User cpu time in clock ticks
+    pub utime: u64,
+    /// System cpu time in clock ticks
+    pub stime: u64,
+}
+
+fn invalid_data(name: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid {}", name))
+}
+
+/// Read file `name` of pid
+pub fn read(pid: Pid, name: &str) -> io::Result<String> {
+    let mut buf = String::new();
+    File::open(format!("/proc/{}/{}", pid, name))?.read_to_string(&mut buf)?;
+    Ok(buf)
+}
+
+/// Read and parse stat of pid
+pub fn stat(pid: Pid) -> io::Result<Stat> {
+    let stat = read(pid, "stat")?;
+    // comm can contain spaces, fields are counted from its closing parenthesis
+    let idx = stat.rfind(')').ok_or(invalid_data("stat"))?;
+    let fields: Vec<&str> = stat[idx + 1..].split_whitespace().collect();
+    let field = |n: usize| fields.get(n).ok_or(invalid_data("stat"));
+    let number = |n: usize| {
+        field(n).and_then(|value| value.parse::<u64>().map_err(|_| invalid_data("stat")))
+    };
+    Ok(Stat {
+        state: field(0)?.chars().next().unwrap_or('?'),
+        ppid: Pid::from_raw(number(1)? as i32),
+        pgrp: Pid::from_raw(number(2)? as i32),
+        utime: number(11)?,
+        stime: number(12)?,
+    })
+}
+
+/// List pids of every process
+pub fn pids() -> io::Result<Vec<Pid>> {
+    Ok(fs::read_dir("/proc")?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .map(Pid::from_raw)
+        .collect())
+}
+
+/// List pids of processes in group pgrp
+pub fn group_members(pgrp: Pid) -> Vec<Pid> {
+    pids()
+        .unwrap_or(Vec::new())
+        .into_iter()
+        .filter(|pid| stat(*pid).map(|stat| stat.pgrp == pgrp).unwrap_or(false))
+        .collect()
+}
+
+/// List inodes of the sockets pid has open
+pub fn socket_inodes(pid: Pid) -> Vec<u64> {
+    let entries = match fs::read_dir(format!("/proc/{}/fd", pid)) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| fs::read_link(entry.path()).ok())
+        .filter_map(|target| {
+            let target = target.to_string_lossy().into_owned();
+            if target.starts_with("socket:[") && target.ends_with(']') {
+                target[8..target.len() - 1].parse::<u64>().ok()
+            } else {
+                None
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,115 @@
+//! Reap children on SIGCHLD, route their exit status to processes and adopt orphans
+
+use ProcessSync;
+
+use failure::Error;
+use nix::errno::Errno;
+use nix::sys::signal::*;
+use nix::sys::wait::*;
+use nix::unistd::*;
+use procfs;
+use self_pipe;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Delay between two lookups of adopted orphans in millis
+const ADOPT_INTERVAL_MS: u64 = 1000;
+
+/// Install SIGCHLD handler and reap children in a dedicated thread
+pub fn start(processes: Arc<Vec<ProcessSync>>) -> Result<(), Error> {
+    let p_read = self_pipe::on_signals(&[Signal::SIGCHLD], SA_RESTART | SA_NOCLDSTOP)?;
+    {
+        let processes = processes.clone();
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_millis(ADOPT_INTERVAL_MS));
+            adopt_orphans(&processes);
+        });
+    }
+    thread::spawn(move || {
+        reap(&processes);
+        let mut buf = [0; 64];
+        loop {
+            match read(p_read, &mut buf) {
+                Ok(_) => reap(&processes),
+                Err(::nix::Error::Sys(Errno::EINTR)) => {}
+                Err(e) => {
+                    critical!("reaper stopped: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Wait every exited child and notify its owner
+fn reap(processes: &[ProcessSync]) {
+    loop {
+        let status = match waitpid(Pid::from_raw(-1), Some(WNOHANG)) {
+            Ok(WaitStatus::StillAlive) => break,
+            Ok(status) => status,
+            Err(::nix::Error::Sys(Errno::ECHILD)) => break,
+            Err(::nix::Error::Sys(Errno::EINTR)) => continue,
+            Err(e) => {
+                warn!("waitpid failed: {}", e);
+                break;
+            }
+        };
+        let pid = match status {
+            WaitStatus::Exited(pid, _) | WaitStatus::Signaled(pid, _, _) => pid,
+            s => {
+                blather!("ignoring status {:?}", s);
+                continue;
+            }
+        };
+        let owner = processes
+            .iter()
+            .find(|p| p.read().unwrap().pid() == Some(pid));
+        match owner {
+            Some(process) => process.read().unwrap().notify_exit(status),
+            None => {
+                let known = processes.iter().any(|p| {
+                    let p = p.read().unwrap();
+                    p.notify_helper(pid, status) || p.notify_orphan_exit(pid)
+                });
+                if !known {
+                    debug!("reaped unknown pid {}", pid);
+                }
+            }
+        }
+    }
+}
+
+/// List live children of the daemon with their process group
+///
+/// Zombies are left out, an exited child waiting to be reaped may be a main pid the reaper
+/// has not reported yet and must not be taken for an orphan.
+pub fn daemon_children() -> Vec<(Pid, Pid)> {
+    let me = getpid();
+    let pids = match procfs::pids() {
+        Ok(pids) => pids,
+        Err(e) => {
+            warn!("cannot list /proc");
+            trace!("error: {}", e);
+            return Vec::new();
+        }
+    };
+    pids.into_iter()
+        .filter_map(|pid| match procfs::stat(pid) {
+            Ok(ref stat) if stat.ppid == me && stat.state != 'Z' => Some((pid, stat.pgrp)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Attribute orphans reparented to the daemon to the processes they come from
+fn adopt_orphans(processes: &[ProcessSync]) {
+    let children = daemon_children();
+    if children.is_empty() {
+        return;
+    }
+    for process in processes.iter() {
+        process.read().unwrap().adopt_orphans(&children);
+    }
+}
@@ -0,0 +1,105 @@
+//! Start scheduled processes at their fire times
+
+use process::*;
+use shutdown::is_shutting_down;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use ProcessSync;
+
+/// Delay between two checks of fire times in millis
+const TICK_MS: u64 = 1000;
+
+fn now_timestamp() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(_) => 0,
+    }
+}
+
+/// Start scheduler for processes having a schedule
+pub fn start(processes: Arc<Vec<ProcessSync>>) {
+    let scheduled: Vec<ProcessSync> = processes
+        .iter()
+        .filter(|p| p.read().unwrap().config().schedule.is_some())
+        .cloned()
+        .collect();
+    if scheduled.is_empty() {
+        return;
+    }
+    let now = now_timestamp();
+    for process in scheduled.iter() {
+        process.read().unwrap().schedule_next(now);
+    }
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(TICK_MS));
+        let now = now_timestamp();
+        for process in scheduled.iter() {
+            let due = match process.read().unwrap().next_run() {
+                Some(next) => next <= now,
+                None => false,
+            };
+            if due {
+                fire(process, now);
+            }
+        }
+    });
+}
+
+/// Start a run of process, unless previous run is active and overlap is not allowed
+///
+/// The previous run is active as long as its supervisor runs, an exited run may be restarted.
+fn fire(process: &ProcessSync, now: i64) {
+    if is_shutting_down() {
+        return;
+    }
+    let (name, claimed, overlap) = {
+        let process = process.read().unwrap();
+        process.schedule_next(now);
+        (
+            process.proc_name().to_owned(),
+            process.claim_supervisor(),
+            process.config().schedule_overlap,
+        )
+    };
+    if claimed {
+        start_scheduled(process);
+        let process = process.clone();
+        thread::spawn(move || supervise(process));
+        return;
+    }
+    if !overlap {
+        warn!(
+            "skipping scheduled run of process {}, previous run is still active",
+            name
+        );
+        return;
+    }
+    info!("starting overlapping run of process {}", name);
+    let process = process.clone();
+    thread::spawn(move || {
+        let started = Instant::now();
+        let spawned = process.read().unwrap().spawn_overlap();
+        let status = match spawned {
+            Ok((pid, receiver)) => {
+                let status = wait_helper(pid, &receiver, None);
+                process.read().unwrap().end_overlap(pid);
+                status
+            }
+            Err(e) => Err(e),
+        };
+        let state = match status {
+            Ok(::nix::sys::wait::WaitStatus::Exited(_, code)) => ProcessState::Exited(code),
+            Ok(::nix::sys::wait::WaitStatus::Signaled(_, sig, _)) => ProcessState::Killed(sig),
+            Ok(_) => ProcessState::Stopped,
+            Err(e) => {
+                error!("overlapping run of process {} failed: {}", name, e);
+                ProcessState::Fatal
+            }
+        };
+        process.read().unwrap().record_run(RunResult {
+            state: state,
+            duration: started.elapsed(),
+        });
+    });
+}
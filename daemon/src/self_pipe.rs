@@ -0,0 +1,50 @@
+//! Self-pipes waking daemon threads from signal handlers or other threads
+
+use failure::Error;
+use nix::libc;
+use nix::sys::signal::*;
+use nix::unistd::*;
+use std::os::unix::io::RawFd;
+use taskmaster::ffi;
+
+/// Number of signals on linux, signal numbers index SIGNAL_FDS
+const NSIG: usize = 65;
+
+/// Write end of the self-pipe of each handled signal, -1 if the signal is not handled
+static mut SIGNAL_FDS: [RawFd; NSIG] = [-1; NSIG];
+
+extern "C" fn handle_signal(signal: libc::c_int) {
+    unsafe {
+        let fd = SIGNAL_FDS[signal as usize];
+        if fd >= 0 {
+            let signal = signal as u8;
+            let _ = libc::write(fd, &signal as *const u8 as *const libc::c_void, 1);
+        }
+    }
+}
+
+/// Open a pipe, both ends are closed on exec and writes never block
+pub fn open() -> Result<(RawFd, RawFd), Error> {
+    let (p_read, p_write) = pipe()?;
+    ffi::set_cloexec(p_read)?;
+    ffi::set_cloexec(p_write)?;
+    ffi::set_nonblocking(p_write)?;
+    Ok((p_read, p_write))
+}
+
+/// Install a handler writing the number of each of signals to a new pipe, return its read end
+pub fn on_signals(signals: &[Signal], flags: SaFlags) -> Result<RawFd, Error> {
+    let (p_read, p_write) = open()?;
+    for signal in signals {
+        unsafe {
+            SIGNAL_FDS[*signal as usize] = p_write;
+        }
+    }
+    let action = SigAction::new(SigHandler::Handler(handle_signal), flags, SigSet::empty());
+    for signal in signals {
+        unsafe {
+            sigaction(*signal, &action)?;
+        }
+    }
+    Ok(p_read)
+}
@@ -0,0 +1,60 @@
+//! Stop processes in reverse priority order and exit on termination signals
+
+use ProcessSync;
+
+use failure::Error;
+use nix::errno::Errno;
+use nix::libc;
+use nix::sys::signal::*;
+use nix::unistd::*;
+use process::stop_processes;
+use self_pipe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// Signals asking the daemon to shut down
+const SIGNALS: [Signal; 2] = [Signal::SIGTERM, Signal::SIGINT];
+
+/// Set once the daemon shuts down, no process is started anymore
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Install handlers of termination signals and shut down in a dedicated thread
+///
+/// Processes run in their own process groups, they are not signaled with the daemon.
+pub fn start(processes: Arc<Vec<ProcessSync>>) -> Result<(), Error> {
+    let p_read = self_pipe::on_signals(&SIGNALS, SA_RESTART)?;
+    thread::spawn(move || {
+        let mut buf = [0; 1];
+        loop {
+            match read(p_read, &mut buf) {
+                Ok(_) => break,
+                Err(::nix::Error::Sys(Errno::EINTR)) => {}
+                Err(e) => {
+                    critical!("shutdown handler stopped: {}", e);
+                    return;
+                }
+            }
+        }
+        match Signal::from_c_int(buf[0] as libc::c_int) {
+            Ok(signal) => warn!("received {:?}, shutting down", signal),
+            Err(_) => warn!("received signal {}, shutting down", buf[0]),
+        }
+        // signals received meanwhile stay in the pipe, the shutdown runs once
+        shutdown(&processes);
+    });
+    Ok(())
+}
+
+/// Is the daemon shutting down
+pub fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::SeqCst)
+}
+
+/// Stop every process and exit, starts requested meanwhile are ignored
+pub fn shutdown(processes: &[ProcessSync]) -> ! {
+    SHUTTING_DOWN.store(true, Ordering::SeqCst);
+    stop_processes(processes);
+    warn!("exiting");
+    ::std::process::exit(0);
+}
@@ -0,0 +1,211 @@
+//! Listening sockets owned by the daemon and passed to programs
+
+use nix::fcntl;
+use nix::sys::stat;
+use nix::unistd::*;
+use procfs;
+use std::fs;
+use std::net::TcpListener;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::io::{IntoRawFd, RawFd};
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use taskmaster::config::{Listen, SocketConfig};
+use taskmaster::ffi::{self, User};
+
+/// First fd of passed sockets, as in systemd socket activation
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Listening socket, kept open across restarts of its program
+#[derive(Debug)]
+pub struct ListenSocket {
+    fd: RawFd,
+    inode: u64,
+    name: String,
+    /// Bound port of a TCP socket
+    port: Option<u16>,
+    /// Bound path of a unix socket
+    path: Option<PathBuf>,
+}
+
+impl ListenSocket {
+    /// Bind socket, name defaults to the program name
+    pub fn bind(config: &SocketConfig, program: &str) -> Result<ListenSocket, String> {
+        let mut port = None;
+        let mut unix_path = None;
+        let fd = match config.listen {
+            Listen::Tcp(ref addr) => {
+                let listener = TcpListener::bind(addr.as_str())
+                    .map_err(|e| format!("cannot bind {}: {}", addr, e))?;
+                port = listener.local_addr().ok().map(|addr| addr.port());
+                listener.into_raw_fd()
+            }
+            Listen::Unix(ref path) => {
+                remove_stale(path);
+                let listener = UnixListener::bind(path)
+                    .map_err(|e| format!("cannot bind {}: {}", path.display(), e))?;
+                if let Some(mode) = config.mode {
+                    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+                        .map_err(|e| format!("cannot set mode of {}: {}", path.display(), e))?;
+                }
+                if let Some(ref owner) = config.owner {
+                    set_owner(path, owner)?;
+                }
+                unix_path = Some(path.clone());
+                listener.into_raw_fd()
+            }
+        };
+        // stdio fds are free after daemonize but overwritten in the child before passing
+        let fd = if fd < LISTEN_FDS_START {
+            let moved = fcntl::fcntl(fd, fcntl::FcntlArg::F_DUPFD_CLOEXEC(LISTEN_FDS_START));
+            let _ = close(fd);
+            moved.map_err(|e| format!("cannot move socket fd {}: {}", fd, e))?
+        } else {
+            fd
+        };
+        let inode = stat::fstat(fd)
+            .map_err(|e| format!("cannot stat socket fd {}: {}", fd, e))?
+            .st_ino as u64;
+        Ok(ListenSocket {
+            fd: fd,
+            inode: inode,
+            name: config.name.clone().unwrap_or_else(|| program.to_owned()),
+            port: port,
+            path: unix_path,
+        })
+    }
+
+    /// Listening file descriptor
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    /// Name passed in LISTEN_FDNAMES
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Drop for ListenSocket {
+    fn drop(&mut self) {
+        let _ = close(self.fd);
+    }
+}
+
+/// Remove a socket left at path by a previous daemon, it would make bind fail
+pub fn remove_stale(path: &Path) {
+    let stale = fs::symlink_metadata(path)
+        .map(|meta| meta.file_type().is_socket())
+        .unwrap_or(false);
+    if stale {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Change owner of path from `user` or `user:group`, group defaults to the user's
+fn set_owner(path: &Path, owner: &str) -> Result<(), String> {
+    let mut parts = owner.splitn(2, ':');
+    let user = parts.next().unwrap_or("");
+    let user = User::lookup(user).ok_or(format!("unknown user `{}`", user))?;
+    let gid = match parts.next() {
+        Some(group) => ffi::lookup_group(group).ok_or(format!("unknown group `{}`", group))?,
+        None => user.gid,
+    };
+    chown(path, Some(user.uid), Some(gid))
+        .map_err(|e| format!("cannot set owner of {}: {}", path.display(), e))
+}
+
+/// Bind every socket of a program
+pub fn bind_all(configs: &[SocketConfig], program: &str) -> Result<Vec<ListenSocket>, String> {
+    configs
+        .iter()
+        .map(|config| ListenSocket::bind(config, program))
+        .collect()
+}
+
+/// Does one of pids hold a connection accepted from sockets
+///
+/// Other sockets, as the ones the program connects to, do not count.
+pub fn has_connections(pids: &[Pid], sockets: &[ListenSocket]) -> bool {
+    let inodes: Vec<u64> = pids
+        .iter()
+        .flat_map(|pid| procfs::socket_inodes(*pid))
+        .filter(|inode| !sockets.iter().any(|socket| socket.inode == *inode))
+        .collect();
+    if inodes.is_empty() {
+        return false;
+    }
+    let ports: Vec<u16> = sockets.iter().filter_map(|socket| socket.port).collect();
+    let paths: Vec<&Path> = sockets
+        .iter()
+        .filter_map(|socket| socket.path.as_ref().map(|path| path.as_path()))
+        .collect();
+    accepted_tcp(&inodes, &ports) || accepted_unix(&inodes, &paths)
+}
+
+/// Is one of inodes a TCP connection on one of the local ports
+fn accepted_tcp(inodes: &[u64], ports: &[u16]) -> bool {
+    if ports.is_empty() {
+        return false;
+    }
+    ["/proc/net/tcp", "/proc/net/tcp6"].iter().any(|table| {
+        fs::read_to_string(table)
+            .unwrap_or(String::new())
+            .lines()
+            .skip(1)
+            .any(|line| {
+                // sl local_address rem_address st ... inode
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                let port = fields
+                    .get(1)
+                    .and_then(|local| local.rsplit(':').next())
+                    .and_then(|port| u16::from_str_radix(port, 16).ok());
+                let inode = fields.get(9).and_then(|inode| inode.parse::<u64>().ok());
+                let listening = fields.get(3) == Some(&"0A");
+                match (port, inode) {
+                    (Some(port), Some(inode)) => {
+                        !listening && ports.contains(&port) && inodes.contains(&inode)
+                    }
+                    _ => false,
+                }
+            })
+    })
+}
+
+/// Is one of inodes a unix connection accepted on one of paths
+fn accepted_unix(inodes: &[u64], paths: &[&Path]) -> bool {
+    if paths.is_empty() {
+        return false;
+    }
+    // accepted sockets are listed with the path of their listening socket
+    fs::read_to_string("/proc/net/unix")
+        .unwrap_or(String::new())
+        .lines()
+        .skip(1)
+        .any(|line| {
+            // Num RefCount Protocol Flags Type St Inode Path
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let inode = fields.get(6).and_then(|inode| inode.parse::<u64>().ok());
+            match (inode, fields.get(7)) {
+                (Some(inode), Some(path)) => {
+                    inodes.contains(&inode) && paths.contains(&Path::new(path))
+                }
+                _ => false,
+            }
+        })
+}
+
+/// Move sockets to consecutive fds from 3 in a child about to exec
+pub fn pass(sockets: &[ListenSocket]) -> ::nix::Result<()> {
+    let end = LISTEN_FDS_START + sockets.len() as RawFd;
+    // copies above the target range so no socket is overwritten while moving
+    let mut fds = Vec::new();
+    for socket in sockets {
+        fds.push(fcntl::fcntl(socket.fd, fcntl::FcntlArg::F_DUPFD(end))?);
+    }
+    for (i, fd) in fds.into_iter().enumerate() {
+        dup2(fd, LISTEN_FDS_START + i as RawFd)?;
+        close(fd)?;
+    }
+    Ok(())
+}
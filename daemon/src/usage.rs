@@ -0,0 +1,144 @@
+//! Sample memory and cpu usage of running processes and restart the ones above their limits
+
+use nix::unistd::{sysconf, Pid, SysconfVar};
+use process::{restart, ProcessState};
+use procfs;
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use taskmaster::config::HumanNumber;
+use taskmaster::ffi;
+use ProcessSync;
+
+/// Delay between two samples in millis
+const SAMPLE_INTERVAL_MS: u64 = 1000;
+
+/// Last sample of a process
+struct Sample {
+    cpu_ticks: u64,
+    at: Instant,
+    over_since: Option<Instant>,
+}
+
+/// Read resident memory of pid in bytes
+fn read_rss(pid: Pid, page_size: u64) -> io::Result<u64> {
+    procfs::read(pid, "statm")?
+        .split_whitespace()
+        .nth(1)
+        .and_then(|pages| pages.parse::<u64>().ok())
+        .map(|pages| pages * page_size)
+        .ok_or(io::Error::new(io::ErrorKind::InvalidData, "invalid statm"))
+}
+
+/// Read user and system cpu time of pid in clock ticks
+fn read_cpu_ticks(pid: Pid) -> io::Result<u64> {
+    procfs::stat(pid).map(|stat| stat.utime + stat.stime)
+}
+
+fn as_secs_f64(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1_000_000_000.0
+}
+
+/// Start sampling processes with max_rss or max_cpu_percent
+pub fn start(processes: Arc<Vec<ProcessSync>>) {
+    let page_size = match sysconf(SysconfVar::PAGE_SIZE) {
+        Ok(Some(size)) => size as u64,
+        _ => 4096,
+    };
+    let clock_ticks = ffi::clock_ticks().unwrap_or(100) as f64;
+    thread::spawn(move || {
+        let mut samples: HashMap<Pid, Sample> = HashMap::new();
+        loop {
+            thread::sleep(Duration::from_millis(SAMPLE_INTERVAL_MS));
+            let mut alive = Vec::new();
+            for process in processes.iter() {
+                let (pid, max_rss, max_cpu, grace) = {
+                    let process = process.read().unwrap();
+                    let config = process.config();
+                    if config.max_rss.is_none() && config.max_cpu_percent.is_none() {
+                        continue;
+                    }
+                    let state = process.get_state().clone();
+                    match state {
+                        ProcessState::Running(pid) => (
+                            pid,
+                            config.max_rss,
+                            config.max_cpu_percent,
+                            Duration::from_secs(config.usage_grace_secs),
+                        ),
+                        _ => continue,
+                    }
+                };
+                alive.push(pid);
+                let now = Instant::now();
+                let ticks = match read_cpu_ticks(pid) {
+                    Ok(ticks) => ticks,
+                    Err(_) => continue,
+                };
+                let sample = samples.entry(pid).or_insert(Sample {
+                    cpu_ticks: ticks,
+                    at: now,
+                    over_since: None,
+                });
+                // ticks only go backwards when pid was reused by a new process
+                if ticks < sample.cpu_ticks {
+                    *sample = Sample {
+                        cpu_ticks: ticks,
+                        at: now,
+                        over_since: None,
+                    };
+                }
+                let mut over = None;
+                if let (Some(max), Ok(rss)) = (max_rss, read_rss(pid, page_size)) {
+                    if rss > max {
+                        over = Some(format!(
+                            "uses {} of memory, above max_rss {}",
+                            rss.to_human(),
+                            max.to_human()
+                        ));
+                    }
+                }
+                let elapsed = as_secs_f64(now - sample.at);
+                if let Some(max) = max_cpu {
+                    if elapsed > 0.0 {
+                        let used = (ticks - sample.cpu_ticks) as f64 / clock_ticks;
+                        let percent = used / elapsed * 100.0;
+                        if percent > max as f64 {
+                            over = Some(format!(
+                                "uses {:.0}% of cpu, above max_cpu_percent {}%",
+                                percent, max
+                            ));
+                        }
+                    }
+                }
+                sample.cpu_ticks = ticks;
+                sample.at = now;
+                let reason = match over {
+                    Some(reason) => reason,
+                    None => {
+                        sample.over_since = None;
+                        continue;
+                    }
+                };
+                let since = *sample.over_since.get_or_insert(now);
+                if now - since < grace {
+                    continue;
+                }
+                sample.over_since = None;
+                let process = process.clone();
+                thread::spawn(move || {
+                    warn!(
+                        "process {} {} for {}s, restarting",
+                        process.read().unwrap().proc_name(),
+                        reason,
+                        grace.as_secs()
+                    );
+                    restart(&process);
+                });
+            }
+            samples.retain(|pid, _| alive.contains(pid));
+        }
+    });
+}
@@ -71,7 +71,9 @@ pub enum ApiKind {
     /// Request server shutdown
     Shutdown => "shutdown",
     /// Request server version
-    Version => "version"
+    Version => "version",
+    /// Request result of the last run of processes
+    LastRun => "last_run"
 });
 
 impl_enum_str! (
@@ -254,6 +256,11 @@ fn send_size<S: Read + Write>(stream: &mut S, size: usize) -> io::Result<()> {
     Ok(())
 }
 
+/// Tag of a foreground chunk holding bytes to write on stdin
+pub const FG_INPUT: u8 = 0;
+/// Tag of a foreground chunk holding rows and columns as big endian u16
+pub const FG_RESIZE: u8 = 1;
+
 /// Send chunk of data
 pub fn send_data<S: Read + Write>(stream: &mut S, data: &[u8]) -> io::Result<()> {
     let data = data.as_ref();
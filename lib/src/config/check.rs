@@ -0,0 +1,125 @@
+use super::*;
+use std::str::FromStr;
+
+fn default_ready_timeout() -> u64 {
+    30
+}
+
+fn default_health_interval() -> u64 {
+    10
+}
+
+fn default_health_timeout() -> u64 {
+    5
+}
+
+fn default_health_failures() -> u32 {
+    3
+}
+
+/// Check run against a program
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Check {
+    /// Connect to a TCP address, as `host:port`
+    Tcp(String),
+    /// Unix socket exists at path
+    UnixSocket(PathBuf),
+    /// File exists at path
+    File(PathBuf),
+    /// Command exits with 0
+    Command(String),
+    /// HTTP GET answers with a 2xx or 3xx status, as `host:port/path`
+    Http(String),
+}
+
+impl FromStr for Check {
+    type Err = String;
+
+    /// Parse check from `kind:value`, e.g. `tcp:127.0.0.1:8080`
+    fn from_str(s: &str) -> Result<Check, String> {
+        let idx = match s.find(':') {
+            Some(idx) => idx,
+            None => return Err(format!("missing check kind in `{}`", s)),
+        };
+        let value = s[idx + 1..].trim();
+        match &s[..idx] {
+            "tcp" => Ok(Check::Tcp(value.to_owned())),
+            "unix_socket" => Ok(Check::UnixSocket(PathBuf::from(value))),
+            "file" => Ok(Check::File(PathBuf::from(value))),
+            "command" => Ok(Check::Command(value.to_owned())),
+            "http" => Ok(Check::Http(value.to_owned())),
+            kind => Err(format!("unknown check kind `{}`", kind)),
+        }
+    }
+}
+
+/// Readiness probe, gates the transition from starting to running
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ReadinessConfig {
+    /// Check that must pass
+    pub check: Check,
+    /// Max secs after spawn for the check to pass, a failed start otherwise
+    #[serde(default = "default_ready_timeout")]
+    pub timeout: u64,
+}
+
+impl ReadinessConfig {
+    /// Create readiness probe with default timeout
+    pub fn new(check: Check) -> ReadinessConfig {
+        ReadinessConfig {
+            check: check,
+            timeout: default_ready_timeout(),
+        }
+    }
+}
+
+/// Periodic liveness check, process is restarted when it fails too many times in a row
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HealthCheckConfig {
+    /// Check that must pass
+    pub check: Check,
+    /// Secs between two checks
+    #[serde(default = "default_health_interval")]
+    pub interval: u64,
+    /// Max secs for a check to pass
+    #[serde(default = "default_health_timeout")]
+    pub timeout: u64,
+    /// Consecutive failed checks before restarting
+    #[serde(default = "default_health_failures")]
+    pub failures: u32,
+}
+
+impl HealthCheckConfig {
+    /// Create health check with default settings
+    pub fn new(check: Check) -> HealthCheckConfig {
+        HealthCheckConfig {
+            check: check,
+            interval: default_health_interval(),
+            timeout: default_health_timeout(),
+            failures: default_health_failures(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_test {
+    use super::*;
+
+    #[test]
+    fn parse_check() {
+        match "tcp:127.0.0.1:8080".parse() {
+            Ok(Check::Tcp(addr)) => assert_eq!(addr, "127.0.0.1:8080"),
+            c => panic!("unexpected check {:?}", c),
+        }
+        match "command: test -e /tmp/ready".parse() {
+            Ok(Check::Command(cmd)) => assert_eq!(cmd, "test -e /tmp/ready"),
+            c => panic!("unexpected check {:?}", c),
+        }
+        match "http:localhost:8080/health".parse() {
+            Ok(Check::Http(url)) => assert_eq!(url, "localhost:8080/health"),
+            c => panic!("unexpected check {:?}", c),
+        }
+        assert!("udp:localhost:53".parse::<Check>().is_err());
+        assert!("/tmp/ready".parse::<Check>().is_err());
+    }
+}
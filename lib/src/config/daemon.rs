@@ -32,6 +32,22 @@ fn default_minfds() -> i32 {
     1024
 }
 
+fn default_cgroup_parent() -> PathBuf {
+    PathBuf::from("/sys/fs/cgroup/taskmaster")
+}
+
+fn default_notify_dir() -> PathBuf {
+    // a runtime directory of the daemon user, others must not reach the sockets
+    let uid = ::nix::unistd::geteuid();
+    if uid.is_root() {
+        return PathBuf::from("/run/taskmasterd/notify");
+    }
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("taskmasterd-notify"),
+        None => env::temp_dir().join(format!("taskmasterd-notify-{}", uid)),
+    }
+}
+
 /// Configuration for taskmasterd
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct DaemonConfig {
@@ -70,6 +86,12 @@ pub struct DaemonConfig {
     #[serde(default)]
     // TODO: gen child log dir
     pub child_log_dir: PathBuf,
+    /// Cgroup v2 under which a cgroup is created for each process
+    #[serde(default = "default_cgroup_parent")]
+    pub cgroup_parent: PathBuf,
+    /// Directory of the NOTIFY_SOCKET of each process, must be owned by the daemon user
+    #[serde(default = "default_notify_dir")]
+    pub notify_dir: PathBuf,
 }
 
 impl Default for DaemonConfig {
@@ -86,6 +108,8 @@ impl Default for DaemonConfig {
             minfds: default_minfds(),
             nocleanup: false,
             child_log_dir: cwd.join("tmp.5321"),
+            cgroup_parent: default_cgroup_parent(),
+            notify_dir: default_notify_dir(),
         }
     }
 }
@@ -0,0 +1,187 @@
+//! Environment of programs
+
+use super::ProcessConfig;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+
+/// Expand `%(ENV_X)s` and `${X}` with variables of the daemon environment
+pub fn expand_env(s: &str) -> Result<String, String> {
+    let mut expanded = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find(|c| c == '%' || c == '$') {
+        expanded.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let (name, len) = if rest.starts_with("%(ENV_") {
+            match rest.find(")s") {
+                Some(end) => (&rest[6..end], end + 2),
+                None => return Err(format!("unterminated variable in `{}`", s)),
+            }
+        } else if rest.starts_with("${") {
+            match rest.find('}') {
+                Some(end) => (&rest[2..end], end + 1),
+                None => return Err(format!("unterminated variable in `{}`", s)),
+            }
+        } else {
+            expanded.push_str(&rest[..1]);
+            rest = &rest[1..];
+            continue;
+        };
+        match env::var(name) {
+            Ok(value) => expanded.push_str(&value),
+            Err(_) => return Err(format!("undefined variable `{}`", name)),
+        }
+        rest = &rest[len..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Parse `KEY="value",KEY2=value2`, commas are allowed in quoted values
+pub fn parse_environment(s: &str) -> Result<BTreeMap<String, String>, String> {
+    let mut vars = BTreeMap::new();
+    let mut chars = s.chars().peekable();
+    loop {
+        let mut name = String::new();
+        let mut has_value = false;
+        for c in chars.by_ref() {
+            if c == '=' {
+                has_value = true;
+                break;
+            }
+            name.push(c);
+        }
+        let name = name.trim().to_string();
+        if name.is_empty() && !has_value {
+            break;
+        }
+        if name.is_empty() || !has_value {
+            return Err(format!("invalid environment `{}`", s));
+        }
+        while chars.peek().map_or(false, |c| c.is_whitespace()) {
+            chars.next();
+        }
+        let value = match chars.peek().cloned() {
+            Some(quote) if quote == '"' || quote == '\'' => {
+                chars.next();
+                let value = chars.by_ref().take_while(|&c| c != quote).collect();
+                while chars.next().map_or(false, |c| c != ',') {}
+                value
+            }
+            _ => chars
+                .by_ref()
+                .take_while(|&c| c != ',')
+                .collect::<String>()
+                .trim()
+                .to_string(),
+        };
+        vars.insert(name, value);
+    }
+    Ok(vars)
+}
+
+/// Parse variables of a dotenv file, values are expanded unless single quoted
+pub fn parse_dotenv(content: &str) -> Result<Vec<(String, String)>, String> {
+    let mut vars = Vec::new();
+    for (n, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = if line.starts_with("export ") {
+            line[7..].trim()
+        } else {
+            line
+        };
+        let (name, value) = match line.find('=') {
+            Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+            None => return Err(format!("line {}: missing `=`", n + 1)),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Err(format!("line {}: invalid name `{}`", n + 1, name));
+        }
+        let quoted = |quote| value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote);
+        let value = if quoted('\'') {
+            Ok(value[1..value.len() - 1].to_string())
+        } else if quoted('"') {
+            expand_env(
+                &value[1..value.len() - 1]
+                    .replace("\\n", "\n")
+                    .replace("\\\"", "\""),
+            )
+        } else {
+            match value.find(" #") {
+                Some(i) => expand_env(value[..i].trim()),
+                None => expand_env(value),
+            }
+        };
+        match value {
+            Ok(value) => vars.push((name.to_string(), value)),
+            Err(e) => return Err(format!("line {}: {}", n + 1, e)),
+        }
+    }
+    Ok(vars)
+}
+
+impl ProcessConfig {
+    /// Variables to set for the program, from envs then env files then environment
+    pub fn env_vars(&self) -> Result<Vec<(String, String)>, String> {
+        let mut vars = Vec::new();
+        for var in self.envs.iter().flat_map(|envs| envs.iter()) {
+            let var = expand_env(var)?;
+            match var.find('=') {
+                Some(i) => vars.push((var[..i].to_string(), var[i + 1..].to_string())),
+                None => vars.push((var, String::new())),
+            }
+        }
+        for path in &self.env_file {
+            let mut content = String::new();
+            File::open(path)
+                .and_then(|mut file| file.read_to_string(&mut content))
+                .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+            let file_vars =
+                parse_dotenv(&content).map_err(|e| format!("{}: {}", path.display(), e))?;
+            vars.extend(file_vars);
+        }
+        for (name, value) in &self.environment {
+            vars.push((name.clone(), expand_env(value)?));
+        }
+        Ok(vars)
+    }
+}
+
+#[cfg(test)]
+mod unit_test {
+    use super::*;
+
+    #[test]
+    fn expand_variables() {
+        env::set_var("TASKMASTER_TEST_VAR", "value");
+        assert_eq!(
+            expand_env("a %(ENV_TASKMASTER_TEST_VAR)s ${TASKMASTER_TEST_VAR} 100%").unwrap(),
+            "a value value 100%"
+        );
+        assert!(expand_env("${TASKMASTER_TEST_UNDEFINED}").is_err());
+        assert!(expand_env("${TASKMASTER_TEST_VAR").is_err());
+    }
+
+    #[test]
+    fn parse_variables() {
+        let vars = parse_environment("A=1, B=\"x,y\",C='z'").unwrap();
+        assert_eq!(vars["A"], "1");
+        assert_eq!(vars["B"], "x,y");
+        assert_eq!(vars["C"], "z");
+        assert!(parse_environment("A=1,B").is_err());
+        let vars = parse_dotenv("# comment\nexport A=1 # one\nB='${A}'\nC=\"a\\nb\"\n").unwrap();
+        assert_eq!(
+            vars,
+            vec![
+                ("A".to_string(), "1".to_string()),
+                ("B".to_string(), "${A}".to_string()),
+                ("C".to_string(), "a\nb".to_string()),
+            ]
+        );
+        assert!(parse_dotenv("A").is_err());
+    }
+}
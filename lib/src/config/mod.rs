@@ -1,17 +1,28 @@
 //! Module to parse and get config
 
+mod check;
 mod ctl;
 mod daemon;
+mod environment;
 mod parser;
 mod process;
+mod rlimit;
+mod schedule;
+mod socket;
 mod util;
 
+pub use self::check::*;
 pub use self::ctl::*;
 pub use self::daemon::*;
+pub use self::environment::*;
 pub use self::parser::*;
 pub use self::process::*;
+pub use self::rlimit::*;
+pub use self::schedule::*;
+pub use self::socket::*;
 pub use self::util::*;
 
+use failure::{err_msg, Error};
 use log::Level;
 use signal::StopSignal;
 use std::env;
@@ -35,6 +46,43 @@ impl Default for AutoRestartCondition {
     }
 }
 
+/// Kind of program
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum ProgramKind {
+    /// Long running program
+    #[serde(alias = "service")]
+    Service,
+    /// Task completed once it exits with an expected code
+    #[serde(alias = "oneshot")]
+    Oneshot,
+    /// Long running program, running once it sends READY=1 on its NOTIFY_SOCKET
+    #[serde(alias = "notify")]
+    Notify,
+}
+
+impl Default for ProgramKind {
+    fn default() -> Self {
+        ProgramKind::Service
+    }
+}
+
+/// What starts a program
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum StartOn {
+    /// Started with the daemon
+    #[serde(alias = "boot")]
+    Boot,
+    /// Started on the first connection to one of its sockets
+    #[serde(alias = "socket")]
+    Socket,
+}
+
+impl Default for StartOn {
+    fn default() -> Self {
+        StartOn::Boot
+    }
+}
+
 /// Logging output
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum OutputLog {
@@ -75,4 +123,91 @@ impl Config {
     pub fn processes(&self) -> &Vec<ProcessConfig> {
         &self.processes
     }
+
+    /// Check that every dependency exists and that there is no dependency cycle
+    pub fn check_dependencies(&self) -> Result<(), Error> {
+        for process in self.processes.iter() {
+            for dep in process.depends_on.iter() {
+                if !self.processes.iter().any(|p| &p.name == dep) {
+                    return Err(err_msg(format!(
+                        "program {} depends on unknown program {}",
+                        process.name, dep
+                    )));
+                }
+            }
+        }
+        let mut visited = Vec::new();
+        for process in self.processes.iter() {
+            self.visit_dependencies(&process.name, &mut Vec::new(), &mut visited)?;
+        }
+        Ok(())
+    }
+
+    fn visit_dependencies<'a>(
+        &'a self,
+        name: &'a str,
+        path: &mut Vec<&'a str>,
+        visited: &mut Vec<&'a str>,
+    ) -> Result<(), Error> {
+        if visited.contains(&name) {
+            return Ok(());
+        }
+        if path.contains(&name) {
+            path.push(name);
+            return Err(err_msg(format!(
+                "dependency cycle between programs: {}",
+                path.join(" -> ")
+            )));
+        }
+        path.push(name);
+        if let Some(process) = self.processes.iter().find(|p| p.name == name) {
+            for dep in process.depends_on.iter() {
+                self.visit_dependencies(dep, path, visited)?;
+            }
+        }
+        path.pop();
+        visited.push(name);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod unit_test {
+    use super::*;
+
+    fn program(name: &str, depends_on: &[&str]) -> ProcessConfig {
+        let mut config = ProcessConfig::default();
+        config.name = name.to_owned();
+        config.depends_on = depends_on.iter().map(|s| s.to_string()).collect();
+        config
+    }
+
+    #[test]
+    fn test_dependencies_ok() {
+        let mut config = Config::default();
+        config.processes = vec![
+            program("api", &["cache", "db"]),
+            program("cache", &["db"]),
+            program("db", &[]),
+        ];
+        assert!(config.check_dependencies().is_ok());
+    }
+
+    #[test]
+    fn test_dependencies_unknown() {
+        let mut config = Config::default();
+        config.processes = vec![program("api", &["cache"])];
+        assert!(config.check_dependencies().is_err());
+    }
+
+    #[test]
+    fn test_dependencies_cycle() {
+        let mut config = Config::default();
+        config.processes = vec![
+            program("api", &["cache"]),
+            program("cache", &["db"]),
+            program("db", &["api"]),
+        ];
+        assert!(config.check_dependencies().is_err());
+    }
 }
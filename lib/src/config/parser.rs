@@ -93,10 +93,40 @@ macro_rules! nbr {
             warn!("config: invalid field `{}` in section [{}]", $dst_name, $section);
             trace!("config error: {}", e);
             $dst
-        });
+        })
     };
 }
 
+macro_rules! opt_nbr {
+    ($dst:expr, $dst_name:expr, $src:expr, $section:expr) => {
+        match $src.as_str() {
+            "none" => $dst = None,
+            v => match v.parse() {
+                Ok(n) => $dst = Some(n),
+                Err(e) => {
+                    warn!("config: invalid field `{}` in section [{}]", $dst_name, $section);
+                    trace!("config error: {}", e);
+                }
+            },
+        }
+    }
+}
+
+macro_rules! opt_human {
+    ($dst:expr, $dst_name:expr, $src:expr, $section:expr) => {
+        match $src.as_str() {
+            "none" => $dst = None,
+            v => match u64::from_human(v) {
+                Ok(n) => $dst = Some(n),
+                Err(e) => {
+                    warn!("config: invalid field `{}` in section [{}]", $dst_name, $section);
+                    trace!("config error: {}", e);
+                }
+            },
+        }
+    }
+}
+
 macro_rules! boolean {
     ($dst:expr, $dst_name:expr, $src:expr, $section:expr) => {
         match $src.as_str() {
@@ -174,6 +204,8 @@ impl ConfigParser {
                     "minfds" => nbr!(config.minfds, k, v, "taskmasterd"),
                     "nocleanup" => boolean!(config.nocleanup, k, v, "taskmasterd"),
                     "child_log_dir" => config.child_log_dir = PathBuf::from(v),
+                    "cgroup_parent" => config.cgroup_parent = PathBuf::from(v),
+                    "notify_dir" => config.notify_dir = PathBuf::from(v),
                     k => warn!("config: unknown field `{}` in section [taskmasterd]", k),
                 },
                 IniValue::Section(_, _) => unreachable!(),
@@ -212,6 +244,10 @@ impl ConfigParser {
     pub fn parse_process(&mut self, name: String, values: &mut Vec<IniValue>) -> ProcessConfig {
         let mut config = ProcessConfig::default();
         let section_name = format!("program:{}", name);
+        let mut ready_timeout = None;
+        let mut health_interval = None;
+        let mut health_timeout = None;
+        let mut health_failures = None;
         config.name = name.clone();
         while let Some(value) = values.pop() {
             match value {
@@ -266,22 +302,13 @@ impl ConfigParser {
                         }
                     },
                     "stop_wait_secs" => nbr!(config.stop_wait_secs, k, v, section_name),
-                    "stop_as_group" => match v.as_str() {
-                        "none" => config.stop_as_group = None,
-                        _ => config.stop_as_group = Some(v),
-                    },
-                    "kill_as_group" => match v.as_str() {
-                        "none" => config.kill_as_group = None,
-                        _ => config.kill_as_group = Some(v),
-                    },
+                    "stop_as_group" => boolean!(config.stop_as_group, k, v, section_name),
+                    "kill_as_group" => boolean!(config.kill_as_group, k, v, section_name),
                     "user" => match v.as_str() {
                         "none" => config.user = None,
                         _ => config.user = Some(v),
                     },
-                    "redirect_stderr" => match v.as_str() {
-                        "none" => config.redirect_stderr = None,
-                        _ => config.redirect_stderr = Some(PathBuf::from(v)),
-                    },
+                    "redirect_stderr" => boolean!(config.redirect_stderr, k, v, section_name),
                     "stdout_logfile" => match v.as_str() {
                         "none" => config.stdout_logfile = OutputLog::None,
                         "auto" => config.stdout_logfile = OutputLog::Auto,
@@ -327,6 +354,41 @@ impl ConfigParser {
                             )
                         }
                     },
+                    "environment" => match parse_environment(&v) {
+                        Ok(vars) => config.environment = vars,
+                        Err(e) => {
+                            warn!(
+                                "config: invalid field `{}` in section [{}]",
+                                k, section_name
+                            );
+                            trace!("error: {}", e);
+                        }
+                    },
+                    "env_file" => {
+                        config.env_file = v.split(',')
+                            .map(|s| s.trim())
+                            .filter(|s| !s.is_empty())
+                            .map(PathBuf::from)
+                            .collect()
+                    }
+                    "clean_env" => boolean!(config.clean_env, k, v, section_name),
+                    "tty" => boolean!(config.tty, k, v, section_name),
+                    "sockets" => {
+                        match v.split(',')
+                            .filter(|s| !s.trim().is_empty())
+                            .map(|s| s.parse())
+                            .collect()
+                        {
+                            Ok(sockets) => config.sockets = sockets,
+                            Err(e) => {
+                                warn!(
+                                    "config: invalid field `{}` in section [{}]",
+                                    k, section_name
+                                );
+                                trace!("error: {}", e);
+                            }
+                        }
+                    }
                     "directory" => match v.as_str() {
                         "none" => config.directory = None,
                         _ => config.directory = Some(PathBuf::from(v)),
@@ -344,6 +406,112 @@ impl ConfigParser {
                             }
                         },
                     },
+                    "memory_max" => opt_human!(config.memory_max, k, v, section_name),
+                    "cpu_max" => opt_nbr!(config.cpu_max, k, v, section_name),
+                    "pids_max" => opt_nbr!(config.pids_max, k, v, section_name),
+                    "max_rss" => opt_human!(config.max_rss, k, v, section_name),
+                    "max_cpu_percent" => opt_nbr!(config.max_cpu_percent, k, v, section_name),
+                    "usage_grace_secs" => nbr!(config.usage_grace_secs, k, v, section_name),
+                    "schedule" => match v.as_str() {
+                        "none" => config.schedule = None,
+                        _ => match v.parse() {
+                            Ok(schedule) => config.schedule = Some(schedule),
+                            Err(e) => {
+                                warn!(
+                                    "config: invalid field `{}` in section [{}]",
+                                    k, section_name
+                                );
+                                trace!("error: {}", e);
+                            }
+                        },
+                    },
+                    "schedule_overlap" => boolean!(config.schedule_overlap, k, v, section_name),
+                    "type" => match v.as_str() {
+                        "service" => config.kind = ProgramKind::Service,
+                        "oneshot" => config.kind = ProgramKind::Oneshot,
+                        "notify" => config.kind = ProgramKind::Notify,
+                        v => {
+                            warn!(
+                                "config: invalid field `{}` in section [{}]",
+                                k, section_name
+                            );
+                            trace!("user have put value `{}`", v);
+                        }
+                    },
+                    "watchdog_secs" => opt_nbr!(config.watchdog_secs, k, v, section_name),
+                    "notify_timeout_secs" => {
+                        nbr!(config.notify_timeout_secs, k, v, section_name)
+                    }
+                    "start_on" => match v.as_str() {
+                        "boot" => config.start_on = StartOn::Boot,
+                        "socket" => config.start_on = StartOn::Socket,
+                        v => {
+                            warn!(
+                                "config: invalid field `{}` in section [{}]",
+                                k, section_name
+                            );
+                            trace!("user have put value `{}`", v);
+                        }
+                    },
+                    "idle_timeout_secs" => opt_nbr!(config.idle_timeout_secs, k, v, section_name),
+                    "pre_start" => match v.as_str() {
+                        "none" => config.pre_start = None,
+                        _ => config.pre_start = Some(v),
+                    },
+                    "post_start" => match v.as_str() {
+                        "none" => config.post_start = None,
+                        _ => config.post_start = Some(v),
+                    },
+                    "pre_stop" => match v.as_str() {
+                        "none" => config.pre_stop = None,
+                        _ => config.pre_stop = Some(v),
+                    },
+                    "post_stop" => match v.as_str() {
+                        "none" => config.post_stop = None,
+                        _ => config.post_stop = Some(v),
+                    },
+                    "hook_timeout_secs" => nbr!(config.hook_timeout_secs, k, v, section_name),
+                    "depends_on" => {
+                        config.depends_on = v.split(',')
+                            .map(|s| s.trim())
+                            .filter(|s| !s.is_empty())
+                            .map(|s| s.to_string())
+                            .collect()
+                    }
+                    "readiness" => match v.parse() {
+                        Ok(check) => config.readiness = Some(ReadinessConfig::new(check)),
+                        Err(e) => {
+                            warn!(
+                                "config: invalid field `{}` in section [{}]",
+                                k, section_name
+                            );
+                            trace!("error: {}", e);
+                        }
+                    },
+                    "readiness_timeout" => opt_nbr!(ready_timeout, k, v, section_name),
+                    "healthcheck" => match v.parse() {
+                        Ok(check) => config.healthcheck = Some(HealthCheckConfig::new(check)),
+                        Err(e) => {
+                            warn!(
+                                "config: invalid field `{}` in section [{}]",
+                                k, section_name
+                            );
+                            trace!("error: {}", e);
+                        }
+                    },
+                    "healthcheck_interval" => opt_nbr!(health_interval, k, v, section_name),
+                    "healthcheck_timeout" => opt_nbr!(health_timeout, k, v, section_name),
+                    "healthcheck_failures" => opt_nbr!(health_failures, k, v, section_name),
+                    k if k.starts_with("rlimit_") => match config.rlimits.set(&k[7..], &v) {
+                        Ok(_) => {}
+                        Err(e) => {
+                            warn!(
+                                "config: invalid field `{}` in section [{}]",
+                                k, section_name
+                            );
+                            trace!("error: {}", e);
+                        }
+                    },
                     k => warn!(
                         "config: unknown field `{}` in section [{}]",
                         k, section_name
@@ -352,6 +520,14 @@ impl ConfigParser {
                 IniValue::Section(_, _) => unreachable!(),
             }
         }
+        if let Some(readiness) = config.readiness.as_mut() {
+            readiness.timeout = ready_timeout.unwrap_or(readiness.timeout);
+        }
+        if let Some(healthcheck) = config.healthcheck.as_mut() {
+            healthcheck.interval = health_interval.unwrap_or(healthcheck.interval);
+            healthcheck.timeout = health_timeout.unwrap_or(healthcheck.timeout);
+            healthcheck.failures = health_failures.unwrap_or(healthcheck.failures);
+        }
         config
     }
 }
@@ -1,4 +1,5 @@
 use super::*;
+use std::collections::BTreeMap;
 
 fn default_num_procs() -> u16 {
     1
@@ -28,6 +29,18 @@ fn default_stop_wait_secs() -> u64 {
     10
 }
 
+fn default_hook_timeout_secs() -> u64 {
+    60
+}
+
+fn default_notify_timeout_secs() -> u64 {
+    90
+}
+
+fn default_usage_grace_secs() -> u64 {
+    30
+}
+
 fn default_logfile_maxbytes() -> usize {
     50000
 }
@@ -73,18 +86,18 @@ pub struct ProcessConfig {
     /// Stop wait secs
     #[serde(default = "default_stop_wait_secs")]
     pub stop_wait_secs: u64,
-    /// Stop as group
+    /// Send stop signal to the whole process group, implies kill_as_group
     #[serde(default)]
-    pub stop_as_group: Option<String>,
-    /// Kill as group
+    pub stop_as_group: bool,
+    /// Send SIGKILL to the whole process group
     #[serde(default)]
-    pub kill_as_group: Option<String>,
+    pub kill_as_group: bool,
     /// User
     #[serde(default)]
     pub user: Option<String>,
-    /// Redirect stderr
+    /// Send stderr to stdout pipe
     #[serde(default)]
-    pub redirect_stderr: Option<PathBuf>,
+    pub redirect_stderr: bool,
     /// Stdout logfile
     #[serde(default)]
     pub stdout_logfile: OutputLog,
@@ -122,12 +135,98 @@ pub struct ProcessConfig {
     /// set envs
     #[serde(default)]
     pub envs: Option<Vec<String>>,
+    /// Environment variables, values can use `%(ENV_X)s` or `${X}`
+    #[serde(default)]
+    pub environment: BTreeMap<String, String>,
+    /// Files in dotenv format, loaded at each start
+    #[serde(default)]
+    pub env_file: Vec<PathBuf>,
+    /// Do not inherit the daemon environment
+    #[serde(default)]
+    pub clean_env: bool,
+    /// Run process in a pseudo-terminal instead of pipes
+    #[serde(default)]
+    pub tty: bool,
+    /// Sockets bound by the daemon, passed from fd 3 as in systemd socket activation
+    #[serde(default)]
+    pub sockets: Vec<SocketConfig>,
+    /// Restart program if it sends no WATCHDOG=1 for this many secs
+    #[serde(default)]
+    pub watchdog_secs: Option<u64>,
+    /// Max secs a notify program may take to send READY=1
+    #[serde(default = "default_notify_timeout_secs")]
+    pub notify_timeout_secs: u64,
+    /// What starts the program
+    #[serde(default)]
+    pub start_on: StartOn,
+    /// Stop a program started on socket once it has no connection for this many secs
+    #[serde(default)]
+    pub idle_timeout_secs: Option<u64>,
     /// set working directory
     #[serde(default)]
     pub directory: Option<PathBuf>,
     /// set umask
     #[serde(default)]
     pub umask: Option<u16>,
+    /// Programs that must be running before this one starts
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Readiness probe, process is running only once it passes
+    #[serde(default)]
+    pub readiness: Option<ReadinessConfig>,
+    /// Liveness check run while process is running
+    #[serde(default)]
+    pub healthcheck: Option<HealthCheckConfig>,
+    /// Resource limits applied before exec
+    #[serde(default)]
+    pub rlimits: RLimits,
+    /// Cgroup memory.max in bytes
+    #[serde(default)]
+    #[serde(serialize_with = "serialize_human_opt")]
+    #[serde(deserialize_with = "deserialize_human_opt")]
+    pub memory_max: Option<u64>,
+    /// Cgroup cpu.max as percent of one cpu
+    #[serde(default)]
+    pub cpu_max: Option<u32>,
+    /// Cgroup pids.max
+    #[serde(default)]
+    pub pids_max: Option<u64>,
+    /// Max resident memory in bytes, process is restarted above it
+    #[serde(default)]
+    #[serde(serialize_with = "serialize_human_opt")]
+    #[serde(deserialize_with = "deserialize_human_opt")]
+    pub max_rss: Option<u64>,
+    /// Max cpu usage as percent of one cpu, process is restarted above it
+    #[serde(default)]
+    pub max_cpu_percent: Option<u32>,
+    /// Secs max_rss or max_cpu_percent can be exceeded before restarting
+    #[serde(default = "default_usage_grace_secs")]
+    pub usage_grace_secs: u64,
+    /// Cron expression, program is started at scheduled times instead of at boot and a run
+    /// ends at exit whatever auto_restart is
+    #[serde(default)]
+    pub schedule: Option<Schedule>,
+    /// Start a scheduled run even if the previous one is still running
+    #[serde(default)]
+    pub schedule_overlap: bool,
+    /// Kind of program
+    #[serde(default, rename = "type")]
+    pub kind: ProgramKind,
+    /// Command run before each start, failing counts as a failed start
+    #[serde(default)]
+    pub pre_start: Option<String>,
+    /// Command run once process is running
+    #[serde(default)]
+    pub post_start: Option<String>,
+    /// Command run before stopping process
+    #[serde(default)]
+    pub pre_stop: Option<String>,
+    /// Command run after process exits
+    #[serde(default)]
+    pub post_stop: Option<String>,
+    /// Max secs a hook can run before being killed
+    #[serde(default = "default_hook_timeout_secs")]
+    pub hook_timeout_secs: u64,
 }
 
 impl Default for ProcessConfig {
@@ -145,10 +244,10 @@ impl Default for ProcessConfig {
             exit_codes: default_exit_codes(),
             stop_signal: StopSignal::default(),
             stop_wait_secs: default_stop_wait_secs(),
-            stop_as_group: None,
-            kill_as_group: None,
+            stop_as_group: false,
+            kill_as_group: false,
             user: None,
-            redirect_stderr: None,
+            redirect_stderr: false,
             stdout_logfile: OutputLog::default(),
             stdout_logfile_maxbytes: default_logfile_maxbytes(),
             stdout_logfile_backups: default_logfile_backups(),
@@ -160,8 +259,145 @@ impl Default for ProcessConfig {
             stderr_capture_maxbytes: 0,
             stderr_events_enabled: false,
             envs: None,
+            environment: BTreeMap::new(),
+            env_file: Vec::new(),
+            clean_env: false,
+            tty: false,
+            sockets: Vec::new(),
+            watchdog_secs: None,
+            notify_timeout_secs: default_notify_timeout_secs(),
+            start_on: StartOn::default(),
+            idle_timeout_secs: None,
             directory: None,
             umask: None,
+            depends_on: Vec::new(),
+            readiness: None,
+            healthcheck: None,
+            rlimits: RLimits::default(),
+            memory_max: None,
+            cpu_max: None,
+            pids_max: None,
+            max_rss: None,
+            max_cpu_percent: None,
+            usage_grace_secs: default_usage_grace_secs(),
+            schedule: None,
+            schedule_overlap: false,
+            kind: ProgramKind::default(),
+            pre_start: None,
+            post_start: None,
+            pre_stop: None,
+            post_stop: None,
+            hook_timeout_secs: default_hook_timeout_secs(),
+        }
+    }
+}
+
+impl ProcessConfig {
+    /// Expand config to one config per instance, according to num_procs
+    ///
+    /// Instances are named `name:NN`, numbered from num_procs_start, and
+    /// `%(process_num)`, optionally followed by a printf specifier such as `s` or `02d`, is
+    /// replaced by the instance number in command and log paths. A program with a single
+    /// instance keeps its name.
+    pub fn instances(&self) -> Vec<ProcessConfig> {
+        (0..self.num_procs)
+            .map(|i| {
+                let num = self.num_procs_start as u32 + i as u32;
+                let mut config = self.clone();
+                if self.num_procs > 1 {
+                    config.name = format!("{}:{:02}", self.name, num);
+                }
+                config.command = expand_process_num(&self.command, num);
+                config.stdout_logfile = expand_output_log(&self.stdout_logfile, num);
+                config.stderr_logfile = expand_output_log(&self.stderr_logfile, num);
+                config
+            })
+            .collect()
+    }
+}
+
+/// Replace `%(process_num)` by num, formatted by the printf specifier following it
+///
+/// `s` and `d` print num as is, `Nd` pads it with spaces and `0Nd` with zeros to N digits.
+/// The bare token prints num as is.
+fn expand_process_num(s: &str, num: u32) -> String {
+    const TOKEN: &str = "%(process_num)";
+    let mut expanded = String::new();
+    let mut rest = s;
+    while let Some(idx) = rest.find(TOKEN) {
+        expanded.push_str(&rest[..idx]);
+        rest = &rest[idx + TOKEN.len()..];
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (width, spec) = rest.split_at(digits);
+        if spec.starts_with('d') {
+            let padding = width.parse().unwrap_or(0);
+            if width.starts_with('0') {
+                expanded.push_str(&format!("{:01$}", num, padding));
+            } else {
+                expanded.push_str(&format!("{:1$}", num, padding));
+            }
+            rest = &spec[1..];
+        } else {
+            expanded.push_str(&num.to_string());
+            if width.is_empty() && spec.starts_with('s') {
+                rest = &spec[1..];
+            }
         }
     }
+    expanded.push_str(rest);
+    expanded
+}
+
+fn expand_output_log(log: &OutputLog, num: u32) -> OutputLog {
+    match log {
+        &OutputLog::File(ref path) => OutputLog::File(PathBuf::from(expand_process_num(
+            &path.display().to_string(),
+            num,
+        ))),
+        log => log.clone(),
+    }
+}
+
+#[cfg(test)]
+mod unit_test {
+    use super::*;
+
+    #[test]
+    fn test_instances_single() {
+        let mut config = ProcessConfig::default();
+        config.name = "worker".to_owned();
+        config.command = "/bin/worker %(process_num)".to_owned();
+        let instances = config.instances();
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0].name, "worker");
+        assert_eq!(instances[0].command, "/bin/worker 0");
+    }
+
+    #[test]
+    fn test_instances_multiple() {
+        let mut config = ProcessConfig::default();
+        config.name = "worker".to_owned();
+        config.command = "/bin/worker --id %(process_num)".to_owned();
+        config.num_procs = 3;
+        config.num_procs_start = 1;
+        let instances = config.instances();
+        let names = instances
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["worker:01", "worker:02", "worker:03"]);
+        assert_eq!(instances[2].command, "/bin/worker --id 3");
+    }
+
+    #[test]
+    fn test_expand_process_num_specifiers() {
+        assert_eq!(expand_process_num("id=%(process_num)s.", 3), "id=3.");
+        assert_eq!(expand_process_num("id=%(process_num)d", 3), "id=3");
+        assert_eq!(expand_process_num("id=%(process_num)02d", 3), "id=03");
+        assert_eq!(expand_process_num("id=%(process_num)3d", 3), "id=  3");
+        assert_eq!(expand_process_num("%(process_num)02d-%(process_num)s", 12), "12-12");
+        assert_eq!(expand_process_num("log%(process_num)", 7), "log7");
+    }
 }
@@ -0,0 +1,113 @@
+use super::*;
+use ffi::Resource;
+use serde::de;
+use serde::{Deserialize, Deserializer, Serializer};
+use std::u64;
+
+/// Parse a limit, either `unlimited` or a human number
+fn parse_limit(s: &str) -> Result<u64, String> {
+    match s.trim() {
+        "unlimited" => Ok(u64::MAX),
+        s => u64::from_human(s),
+    }
+}
+
+fn deserialize_limit<'de, D>(de: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    String::deserialize(de).and_then(|s| parse_limit(&s).map(Some).map_err(de::Error::custom))
+}
+
+fn serialize_limit<S>(val: &Option<u64>, ser: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match *val {
+        Some(u64::MAX) => ser.serialize_str("unlimited"),
+        Some(ref limit) => ser.serialize_str(&limit.to_human()),
+        None => ser.serialize_none(),
+    }
+}
+
+/// Resource limits of a process, both soft and hard limits are set
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RLimits {
+    /// Max open file descriptors
+    #[serde(default)]
+    #[serde(serialize_with = "serialize_limit")]
+    #[serde(deserialize_with = "deserialize_limit")]
+    pub nofile: Option<u64>,
+    /// Max processes of the user
+    #[serde(default)]
+    #[serde(serialize_with = "serialize_limit")]
+    #[serde(deserialize_with = "deserialize_limit")]
+    pub nproc: Option<u64>,
+    /// Max core dump size
+    #[serde(default)]
+    #[serde(serialize_with = "serialize_limit")]
+    #[serde(deserialize_with = "deserialize_limit")]
+    pub core: Option<u64>,
+    /// Max address space size
+    #[serde(default, rename = "as")]
+    #[serde(serialize_with = "serialize_limit")]
+    #[serde(deserialize_with = "deserialize_limit")]
+    pub address_space: Option<u64>,
+    /// Max cpu time in secs
+    #[serde(default)]
+    #[serde(serialize_with = "serialize_limit")]
+    #[serde(deserialize_with = "deserialize_limit")]
+    pub cpu: Option<u64>,
+    /// Max size of created files
+    #[serde(default)]
+    #[serde(serialize_with = "serialize_limit")]
+    #[serde(deserialize_with = "deserialize_limit")]
+    pub fsize: Option<u64>,
+    /// Max locked memory size
+    #[serde(default)]
+    #[serde(serialize_with = "serialize_limit")]
+    #[serde(deserialize_with = "deserialize_limit")]
+    pub memlock: Option<u64>,
+    /// Max stack size
+    #[serde(default)]
+    #[serde(serialize_with = "serialize_limit")]
+    #[serde(deserialize_with = "deserialize_limit")]
+    pub stack: Option<u64>,
+}
+
+impl RLimits {
+    /// Set limit from its name and value
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let limit = parse_limit(value)?;
+        match name {
+            "nofile" => self.nofile = Some(limit),
+            "nproc" => self.nproc = Some(limit),
+            "core" => self.core = Some(limit),
+            "as" => self.address_space = Some(limit),
+            "cpu" => self.cpu = Some(limit),
+            "fsize" => self.fsize = Some(limit),
+            "memlock" => self.memlock = Some(limit),
+            "stack" => self.stack = Some(limit),
+            name => return Err(format!("unknown rlimit `{}`", name)),
+        }
+        Ok(())
+    }
+
+    /// Get limits that are set
+    pub fn limits(&self) -> Vec<(Resource, u64)> {
+        vec![
+            (Resource::Nofile, self.nofile),
+            (Resource::Nproc, self.nproc),
+            (Resource::Core, self.core),
+            (Resource::As, self.address_space),
+            (Resource::Cpu, self.cpu),
+            (Resource::Fsize, self.fsize),
+            (Resource::Memlock, self.memlock),
+            (Resource::Stack, self.stack),
+        ]
+        .into_iter()
+        .filter_map(|(resource, limit)| limit.map(|limit| (resource, limit)))
+        .collect()
+    }
+}
@@ -0,0 +1,206 @@
+use ffi::LocalTime;
+use serde::de;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::str::FromStr;
+
+/// Max steps when searching the next fire time, enough for a few years
+const MAX_SEARCH_STEPS: usize = 100000;
+
+/// Cron expression with minute, hour, day of month, month and day of week fields
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schedule {
+    expr: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+fn parse_value(s: &str, part: &str) -> Result<u32, String> {
+    s.parse()
+        .map_err(|_| format!("invalid value `{}` in `{}`", s, part))
+}
+
+/// Parse a cron field into a bit set of allowed values
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.find('/') {
+            Some(idx) => (&part[..idx], parse_value(&part[idx + 1..], part)?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(format!("invalid step in `{}`", part));
+        }
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else {
+            match range.find('-') {
+                Some(idx) => (
+                    parse_value(&range[..idx], part)?,
+                    parse_value(&range[idx + 1..], part)?,
+                ),
+                None => {
+                    let value = parse_value(range, part)?;
+                    (value, if step > 1 { max } else { value })
+                }
+            }
+        };
+        if start < min || end > max || start > end {
+            return Err(format!("`{}` is out of range {}-{}", part, min, max));
+        }
+        let mut value = start;
+        while value <= end {
+            set |= 1 << value;
+            value += step;
+        }
+    }
+    Ok(set)
+}
+
+fn has(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+impl Schedule {
+    fn day_matches(&self, time: &LocalTime) -> bool {
+        let day = has(self.days, time.day);
+        let weekday = has(self.weekdays, time.weekday);
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+
+    /// Check if schedule fires at local time
+    pub fn matches(&self, time: &LocalTime) -> bool {
+        has(self.minutes, time.minute)
+            && has(self.hours, time.hour)
+            && has(self.months, time.month)
+            && self.day_matches(time)
+    }
+
+    /// Get next fire time strictly after timestamp, in secs since epoch
+    pub fn next_after(&self, timestamp: i64) -> Option<i64> {
+        let mut t = (timestamp / 60 + 1) * 60;
+        for _ in 0..MAX_SEARCH_STEPS {
+            let time = LocalTime::from_timestamp(t);
+            let next = if !has(self.months, time.month) {
+                LocalTime {
+                    month: time.month + 1,
+                    day: 1,
+                    hour: 0,
+                    minute: 0,
+                    ..time
+                }
+                .timestamp()
+            } else if !self.day_matches(&time) {
+                LocalTime {
+                    day: time.day + 1,
+                    hour: 0,
+                    minute: 0,
+                    ..time
+                }
+                .timestamp()
+            } else if !has(self.hours, time.hour) {
+                LocalTime {
+                    hour: time.hour + 1,
+                    minute: 0,
+                    ..time
+                }
+                .timestamp()
+            } else if !has(self.minutes, time.minute) {
+                t + 60
+            } else {
+                return Some(t);
+            };
+            // a dst change can move the normalized time backward
+            t = if next > t { next } else { t + 60 };
+        }
+        None
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Schedule, String> {
+        let expr = match s.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expr => expr,
+        };
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("expected 5 fields in schedule `{}`", s));
+        }
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        if has(weekdays, 7) {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(Schedule {
+            expr: s.trim().to_owned(),
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays: weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+}
+
+impl Serialize for Schedule {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        ser.serialize_str(&self.expr)
+    }
+}
+
+impl<'de> Deserialize<'de> for Schedule {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Schedule, D::Error> {
+        String::deserialize(de).and_then(|s| s.parse().map_err(de::Error::custom))
+    }
+}
+
+#[cfg(test)]
+mod unit_test {
+    use super::*;
+
+    #[test]
+    fn parse_schedule() {
+        let schedule: Schedule = "*/15 8-18 * * 1-5".parse().unwrap();
+        assert_eq!(schedule.minutes, 1 | 1 << 15 | 1 << 30 | 1 << 45);
+        assert_eq!(schedule.weekdays, 0b111110);
+        let schedule: Schedule = "0 0 * * 7".parse().unwrap();
+        assert_eq!(schedule.weekdays, 1);
+        assert!("* * * *".parse::<Schedule>().is_err());
+        assert!("60 * * * *".parse::<Schedule>().is_err());
+        assert!("*/0 * * * *".parse::<Schedule>().is_err());
+    }
+
+    #[test]
+    fn next_fire_time() {
+        let now = 1_700_000_000;
+        let schedule: Schedule = "*/15 * * * *".parse().unwrap();
+        let next = schedule.next_after(now).unwrap();
+        assert!(next > now && next - now <= 15 * 60);
+        assert_eq!(LocalTime::from_timestamp(next).minute % 15, 0);
+        let schedule: Schedule = "30 4 1 2 *".parse().unwrap();
+        let next = schedule.next_after(now).unwrap();
+        assert!(schedule.matches(&LocalTime::from_timestamp(next)));
+        assert!("0 0 30 2 *"
+            .parse::<Schedule>()
+            .unwrap()
+            .next_after(now)
+            .is_none());
+    }
+}
@@ -0,0 +1,112 @@
+use super::*;
+use std::str::FromStr;
+
+/// Address a daemon-owned socket listens on
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub enum Listen {
+    /// TCP address, as `host:port`
+    Tcp(String),
+    /// Unix socket at path
+    Unix(PathBuf),
+}
+
+impl FromStr for Listen {
+    type Err = String;
+
+    /// Parse address from `kind:value`, e.g. `tcp:127.0.0.1:8080` or `unix:/run/app.sock`
+    fn from_str(s: &str) -> Result<Listen, String> {
+        let idx = match s.find(':') {
+            Some(idx) => idx,
+            None => return Err(format!("missing socket kind in `{}`", s)),
+        };
+        let value = s[idx + 1..].trim();
+        match &s[..idx] {
+            "tcp" => Ok(Listen::Tcp(value.to_owned())),
+            "unix" => Ok(Listen::Unix(PathBuf::from(value))),
+            kind => Err(format!("unknown socket kind `{}`", kind)),
+        }
+    }
+}
+
+/// Listening socket bound by the daemon and passed to the program
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SocketConfig {
+    /// Address to listen on
+    pub listen: Listen,
+    /// Name passed in LISTEN_FDNAMES, defaults to the program name
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Permissions of a unix socket
+    #[serde(default)]
+    #[serde(serialize_with = "serialize_octal_opt")]
+    #[serde(deserialize_with = "deserialize_octal_opt")]
+    pub mode: Option<u32>,
+    /// Owner of a unix socket, as `user` or `user:group`
+    #[serde(default)]
+    pub owner: Option<String>,
+}
+
+impl SocketConfig {
+    /// Create a socket config listening on address
+    pub fn new(listen: Listen) -> SocketConfig {
+        SocketConfig {
+            listen: listen,
+            name: None,
+            mode: None,
+            owner: None,
+        }
+    }
+}
+
+impl FromStr for SocketConfig {
+    type Err = String;
+
+    /// Parse socket from an address followed by options,
+    /// e.g. `unix:/run/app.sock mode=0660 owner=www name=api`
+    fn from_str(s: &str) -> Result<SocketConfig, String> {
+        let mut words = s.split_whitespace();
+        let mut config = match words.next() {
+            Some(listen) => SocketConfig::new(listen.parse()?),
+            None => return Err("missing socket address".to_owned()),
+        };
+        for word in words {
+            let idx = match word.find('=') {
+                Some(idx) => idx,
+                None => return Err(format!("invalid socket option `{}`", word)),
+            };
+            let value = &word[idx + 1..];
+            match &word[..idx] {
+                "name" => config.name = Some(value.to_owned()),
+                "mode" => match parse_octal(value) {
+                    Ok(mode) => config.mode = Some(mode),
+                    Err(e) => return Err(format!("invalid socket mode `{}`: {}", value, e)),
+                },
+                "owner" => config.owner = Some(value.to_owned()),
+                option => return Err(format!("unknown socket option `{}`", option)),
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod unit_test {
+    use super::*;
+
+    #[test]
+    fn parse_socket() {
+        let socket: SocketConfig = "tcp:127.0.0.1:8080".parse().unwrap();
+        assert_eq!(socket.listen, Listen::Tcp("127.0.0.1:8080".to_owned()));
+        assert_eq!(socket.mode, None);
+        let socket: SocketConfig = "unix:/run/app.sock mode=0o660 owner=www:www name=api"
+            .parse()
+            .unwrap();
+        assert_eq!(socket.listen, Listen::Unix(PathBuf::from("/run/app.sock")));
+        assert_eq!(socket.mode, Some(0o660));
+        assert_eq!(socket.owner, Some("www:www".to_owned()));
+        assert_eq!(socket.name, Some("api".to_owned()));
+        assert!("udp:0.0.0.0:53".parse::<SocketConfig>().is_err());
+        assert!("unix:/run/app.sock mode=999".parse::<SocketConfig>().is_err());
+    }
+}
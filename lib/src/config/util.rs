@@ -156,6 +156,37 @@ where
     ser.serialize_str(&format!("{:#o}", val))
 }
 
+/// Parse octal number, with or without `0o` prefix
+pub fn parse_octal<T: IntStrRadix>(s: &str) -> Result<T, ::std::num::ParseIntError> {
+    if s.starts_with("0o") {
+        T::str_radix(&s[2..], 8)
+    } else {
+        T::str_radix(s, 8)
+    }
+}
+
+/// Deserialize optional octal number
+pub fn deserialize_octal_opt<'de, D, T: IntStrRadix>(de: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(de).and_then(|s| match s {
+        Some(s) => parse_octal(&s).map(Some).map_err(|e| de::Error::custom(e)),
+        None => Ok(None),
+    })
+}
+
+/// Serialize optional octal number
+pub fn serialize_octal_opt<S, T: fmt::Octal>(val: &Option<T>, ser: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match *val {
+        Some(ref val) => ser.serialize_str(&format!("{:#o}", val)),
+        None => ser.serialize_none(),
+    }
+}
+
 /// Deserialize human number
 pub fn deserialize_human<'de, D, T: HumanNumber>(de: D) -> Result<T, D::Error>
 where
@@ -171,3 +202,25 @@ where
 {
     ser.serialize_str(&val.to_human())
 }
+
+/// Deserialize optional human number
+pub fn deserialize_human_opt<'de, D, T: HumanNumber>(de: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(de).and_then(|s| match s {
+        Some(s) => T::from_human(&s).map(Some).map_err(|e| de::Error::custom(e)),
+        None => Ok(None),
+    })
+}
+
+/// Serialize optional human number
+pub fn serialize_human_opt<S, T: HumanNumber>(val: &Option<T>, ser: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match *val {
+        Some(ref val) => ser.serialize_str(&val.to_human()),
+        None => ser.serialize_none(),
+    }
+}
@@ -1,6 +1,15 @@
 #![allow(missing_docs)]
 
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg, OFlag, FD_CLOEXEC, O_NONBLOCK};
+use nix::libc;
+use nix::poll::{poll, PollFd, POLLOUT};
 use nix::unistd::*;
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::os::unix::io::RawFd;
+use std::path::PathBuf;
+use std::ptr;
 
 /// Close all file descriptors
 pub fn close_all_fd() {
@@ -9,3 +18,301 @@ pub fn close_all_fd() {
         let _ = close(i as i32);
     }
 }
+
+/// Close fd on exec
+pub fn set_cloexec(fd: RawFd) -> ::nix::Result<()> {
+    fcntl(fd, FcntlArg::F_SETFD(FD_CLOEXEC)).map(drop)
+}
+
+/// Make reads and writes on fd return EAGAIN instead of blocking
+pub fn set_nonblocking(fd: RawFd) -> ::nix::Result<()> {
+    let flags = OFlag::from_bits_truncate(fcntl(fd, FcntlArg::F_GETFL)?);
+    fcntl(fd, FcntlArg::F_SETFL(flags | O_NONBLOCK)).map(drop)
+}
+
+/// Write all of buf to fd, retrying on partial writes and interrupts
+///
+/// fd may be non-blocking, as O_NONBLOCK is shared by every dup of a pty master, so a full
+/// buffer waits until fd is writable again
+pub fn write_all(fd: RawFd, mut buf: &[u8]) -> ::nix::Result<()> {
+    while !buf.is_empty() {
+        match write(fd, buf) {
+            Ok(size) => buf = &buf[size..],
+            Err(::nix::Error::Sys(Errno::EINTR)) => {}
+            Err(::nix::Error::Sys(Errno::EAGAIN)) => {
+                match poll(&mut [PollFd::new(fd, POLLOUT)], -1) {
+                    Ok(_) | Err(::nix::Error::Sys(Errno::EINTR)) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Entry of the passwd database
+#[derive(Clone, Debug)]
+pub struct User {
+    pub name: String,
+    pub uid: Uid,
+    pub gid: Gid,
+    pub home: PathBuf,
+}
+
+impl User {
+    fn from_passwd(pwd: &libc::passwd) -> User {
+        unsafe {
+            User {
+                name: CStr::from_ptr(pwd.pw_name).to_string_lossy().into_owned(),
+                uid: Uid::from_raw(pwd.pw_uid),
+                gid: Gid::from_raw(pwd.pw_gid),
+                home: PathBuf::from(CStr::from_ptr(pwd.pw_dir).to_string_lossy().into_owned()),
+            }
+        }
+    }
+
+    /// Find user by name, or by uid if name is numeric
+    pub fn lookup(name: &str) -> Option<User> {
+        let mut pwd: libc::passwd = unsafe { mem::zeroed() };
+        let mut res = ptr::null_mut();
+        let mut buf = vec![0 as libc::c_char; 16384];
+        let ret = match name.parse::<libc::uid_t>() {
+            Ok(uid) => unsafe {
+                libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut res)
+            },
+            Err(_) => {
+                let c_name = CString::new(name).ok()?;
+                unsafe {
+                    libc::getpwnam_r(
+                        c_name.as_ptr(),
+                        &mut pwd,
+                        buf.as_mut_ptr(),
+                        buf.len(),
+                        &mut res,
+                    )
+                }
+            }
+        };
+        if ret != 0 || res.is_null() {
+            return None;
+        }
+        Some(User::from_passwd(&pwd))
+    }
+}
+
+/// Find group id by name, or parse it if name is numeric
+pub fn lookup_group(name: &str) -> Option<Gid> {
+    if let Ok(gid) = name.parse::<libc::gid_t>() {
+        return Some(Gid::from_raw(gid));
+    }
+    let mut grp: libc::group = unsafe { mem::zeroed() };
+    let mut res = ptr::null_mut();
+    let mut buf = vec![0 as libc::c_char; 16384];
+    let c_name = CString::new(name).ok()?;
+    let ret = unsafe {
+        libc::getgrnam_r(
+            c_name.as_ptr(),
+            &mut grp,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut res,
+        )
+    };
+    if ret != 0 || res.is_null() {
+        return None;
+    }
+    Some(Gid::from_raw(grp.gr_gid))
+}
+
+/// Set supplementary groups of the calling process from the group database
+pub fn initgroups(user: &str, gid: Gid) -> ::nix::Result<()> {
+    let c_user = CString::new(user).map_err(|_| ::nix::Error::invalid_argument())?;
+    let gid: libc::gid_t = gid.into();
+    let res = unsafe { libc::initgroups(c_user.as_ptr(), gid as _) };
+    Errno::result(res).map(drop)
+}
+
+/// Resource limited with setrlimit
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resource {
+    Nofile,
+    Nproc,
+    Core,
+    As,
+    Cpu,
+    Fsize,
+    Memlock,
+    Stack,
+}
+
+impl Resource {
+    fn raw(&self) -> libc::c_int {
+        (match *self {
+            Resource::Nofile => libc::RLIMIT_NOFILE,
+            Resource::Nproc => libc::RLIMIT_NPROC,
+            Resource::Core => libc::RLIMIT_CORE,
+            Resource::As => libc::RLIMIT_AS,
+            Resource::Cpu => libc::RLIMIT_CPU,
+            Resource::Fsize => libc::RLIMIT_FSIZE,
+            Resource::Memlock => libc::RLIMIT_MEMLOCK,
+            Resource::Stack => libc::RLIMIT_STACK,
+        }) as libc::c_int
+    }
+}
+
+/// Get soft and hard limits of resource
+pub fn getrlimit(resource: Resource) -> ::nix::Result<(u64, u64)> {
+    let mut rlim: libc::rlimit = unsafe { mem::zeroed() };
+    let res = unsafe { libc::getrlimit(resource.raw() as _, &mut rlim) };
+    Errno::result(res).map(|_| (rlim.rlim_cur as u64, rlim.rlim_max as u64))
+}
+
+/// Set soft and hard limits of resource, u64::MAX means unlimited
+pub fn setrlimit(resource: Resource, soft: u64, hard: u64) -> ::nix::Result<()> {
+    let rlim = libc::rlimit {
+        rlim_cur: soft as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t,
+    };
+    let res = unsafe { libc::setrlimit(resource.raw() as _, &rlim) };
+    Errno::result(res).map(drop)
+}
+
+/// Get number of clock ticks per second, unit of cpu times in /proc
+pub fn clock_ticks() -> Option<u64> {
+    match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+        ticks if ticks > 0 => Some(ticks as u64),
+        _ => None,
+    }
+}
+
+/// Adopt orphaned descendants instead of init, they are reparented to the calling process
+pub fn set_child_subreaper() -> ::nix::Result<()> {
+    let res = unsafe { libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0) };
+    Errno::result(res).map(drop)
+}
+
+/// Make a unix socket receive the credentials of the sender with each message
+pub fn set_pass_cred(fd: RawFd) -> ::nix::Result<()> {
+    let on: libc::c_int = 1;
+    let res = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_PASSCRED,
+            &on as *const libc::c_int as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    Errno::result(res).map(drop)
+}
+
+/// Receive a message on a socket with SO_PASSCRED, with the pid of its sender
+pub fn recv_with_pid(fd: RawFd, buf: &mut [u8]) -> ::nix::Result<(usize, Option<Pid>)> {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    // u64 keeps the control buffer aligned for cmsghdr
+    let mut control = [0u64; 8];
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = mem::size_of_val(&control) as _;
+    let size = Errno::result(unsafe { libc::recvmsg(fd, &mut msg, 0) })?;
+    let mut pid = None;
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_CREDENTIALS
+            {
+                let cred = ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::ucred);
+                pid = Some(Pid::from_raw(cred.pid));
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    Ok((size as usize, pid))
+}
+
+/// Make tty the controlling terminal of the calling process, which must lead a new session
+pub fn set_controlling_tty(fd: RawFd) -> ::nix::Result<()> {
+    let res = unsafe { libc::ioctl(fd, libc::TIOCSCTTY as _, 0) };
+    Errno::result(res).map(drop)
+}
+
+/// Get rows and columns of a terminal
+pub fn get_winsize(fd: RawFd) -> ::nix::Result<(u16, u16)> {
+    let mut ws: libc::winsize = unsafe { mem::zeroed() };
+    let res = unsafe { libc::ioctl(fd, libc::TIOCGWINSZ as _, &mut ws) };
+    Errno::result(res).map(|_| (ws.ws_row, ws.ws_col))
+}
+
+/// Set rows and columns of a terminal, its foreground process group gets SIGWINCH
+pub fn set_winsize(fd: RawFd, rows: u16, cols: u16) -> ::nix::Result<()> {
+    let ws = libc::winsize {
+        ws_row: rows,
+        ws_col: cols,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let res = unsafe { libc::ioctl(fd, libc::TIOCSWINSZ as _, &ws) };
+    Errno::result(res).map(drop)
+}
+
+/// Broken down local time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LocalTime {
+    pub year: i32,
+    /// 1 to 12
+    pub month: u32,
+    /// 1 to 31
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    /// 0 to 6, sunday is 0
+    pub weekday: u32,
+}
+
+impl LocalTime {
+    /// Convert secs since epoch to local time
+    pub fn from_timestamp(timestamp: i64) -> LocalTime {
+        let time = timestamp as libc::time_t;
+        let mut tm: libc::tm = unsafe { mem::zeroed() };
+        unsafe { libc::localtime_r(&time, &mut tm) };
+        LocalTime {
+            year: tm.tm_year + 1900,
+            month: (tm.tm_mon + 1) as u32,
+            day: tm.tm_mday as u32,
+            hour: tm.tm_hour as u32,
+            minute: tm.tm_min as u32,
+            second: tm.tm_sec as u32,
+            weekday: tm.tm_wday as u32,
+        }
+    }
+
+    /// Convert to secs since epoch, out of range fields are normalized
+    pub fn timestamp(&self) -> i64 {
+        let mut tm: libc::tm = unsafe { mem::zeroed() };
+        tm.tm_year = self.year - 1900;
+        tm.tm_mon = self.month as libc::c_int - 1;
+        tm.tm_mday = self.day as libc::c_int;
+        tm.tm_hour = self.hour as libc::c_int;
+        tm.tm_min = self.minute as libc::c_int;
+        tm.tm_sec = self.second as libc::c_int;
+        tm.tm_isdst = -1;
+        unsafe { libc::mktime(&mut tm) as i64 }
+    }
+}
+
+impl ::std::fmt::Display for LocalTime {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(
+            f,
+            "{}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
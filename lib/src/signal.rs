@@ -27,10 +27,16 @@ pub enum StopSignal {
 }
 
 impl StopSignal {
-    /// Kill a process
-    pub fn kill(&self, pid: Pid) -> Result<(), Error> {
-        trace!("killing pid {} with signal {:?}", pid, self);
-        kill(pid, Some(Signal::from_c_int(*self as i32).unwrap())).map_err(|e| e.into())
+    /// Kill a process, or its whole process group if group is true
+    pub fn kill(&self, pid: Pid, group: bool) -> Result<(), Error> {
+        let signal = Some(Signal::from_c_int(*self as i32).unwrap());
+        if group {
+            trace!("killing process group {} with signal {:?}", pid, self);
+            kill(Pid::from_raw(-libc::pid_t::from(pid)), signal).map_err(|e| e.into())
+        } else {
+            trace!("killing pid {} with signal {:?}", pid, self);
+            kill(pid, signal).map_err(|e| e.into())
+        }
     }
 }
 